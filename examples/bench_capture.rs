@@ -0,0 +1,52 @@
+//! Measures the overhead of the capture functions.
+//!
+//! Run with `cargo run --release --example bench_capture [iterations]`.
+extern crate defeat;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use defeat::Backtrace;
+
+fn measure<F: FnMut() -> Option<Backtrace>>(iterations: u32, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
+
+fn report(name: &str, per_call: Duration) {
+    println!("{:<28} {:>10.2?}", name, per_call);
+}
+
+fn main() {
+    let iterations = env::args()
+        .nth(1)
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(1000);
+
+    // the auto mode picks its strategy from the environment
+    env::remove_var("RUST_BACKTRACE");
+    // warm up the symbol and module caches
+    Backtrace::capture().map(|bt| bt.trimmed());
+
+    report("capture", measure(iterations, Backtrace::capture));
+    report(
+        "capture_light",
+        measure(iterations, Backtrace::capture_light),
+    );
+    report(
+        "capture_auto (off)",
+        measure(iterations, Backtrace::capture_auto),
+    );
+    report(
+        "capture + trimmed",
+        measure(iterations, || Backtrace::capture().map(|bt| bt.trimmed())),
+    );
+    env::set_var("RUST_BACKTRACE", "1");
+    report(
+        "capture_auto (enabled)",
+        measure(iterations, Backtrace::capture_auto),
+    );
+}
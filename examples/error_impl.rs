@@ -0,0 +1,75 @@
+extern crate defeat;
+
+use std::fmt;
+use std::io;
+
+use defeat::{Backtrace, CapturePurpose, Error};
+
+/// An error wired up the way a derive would do it.
+#[derive(Debug)]
+enum ConfigError {
+    Missing {
+        key: String,
+    },
+    Io {
+        // the origin of the error
+        error: io::Error,
+        backtrace: Option<Backtrace>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Missing { ref key } => write!(f, "missing config key {}", key),
+            ConfigError::Io { .. } => write!(f, "could not read config"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn sync_origin(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        match *self {
+            ConfigError::Missing { .. } => None,
+            ConfigError::Io { ref error, .. } => Some(error),
+        }
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        match *self {
+            ConfigError::Missing { .. } => None,
+            ConfigError::Io { ref backtrace, .. } => backtrace.as_ref(),
+        }
+    }
+
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        if let ConfigError::Io {
+            ref mut backtrace, ..
+        } = *self
+        {
+            *backtrace = Some(bt);
+        }
+    }
+}
+
+fn load() -> Result<String, ConfigError> {
+    let missing = ConfigError::Missing { key: "path".into() };
+    println!("{}", missing.context_chain());
+
+    Err(ConfigError::Io {
+        error: io::Error::new(io::ErrorKind::NotFound, "config.toml not found"),
+        backtrace: Backtrace::conditional_capture(CapturePurpose::Error),
+    })
+}
+
+fn main() {
+    let err = load().unwrap_err();
+    println!("{}", err.context_chain());
+    println!(
+        "origin is io error: {}",
+        err.origin().unwrap().is::<io::Error>()
+    );
+    if let Some(bt) = err.backtrace() {
+        println!("{}", bt.clone().trimmed());
+    }
+}
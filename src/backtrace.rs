@@ -1,19 +1,38 @@
-#[cfg(feature = "backtrace")]
-use std::cell::UnsafeCell;
+use std::borrow::Cow;
+use std::cell::Cell;
+#[cfg(any(feature = "intern", target_os = "linux"))]
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
+use std::error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::iter;
+use std::mem;
 use std::os::raw::c_void;
+use std::panic::Location;
 use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
 use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::sync::{OnceLock, RwLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "backtrace")]
 use backtrace_support;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Represents a symbol name.
+#[derive(Clone)]
 pub struct SymbolName<'a> {
     bytes: &'a [u8],
     #[cfg(feature = "backtrace")]
-    demangled: UnsafeCell<Option<String>>,
+    demangled: OnceLock<Arc<str>>,
 }
 
 impl<'a> SymbolName<'a> {
@@ -23,9 +42,9 @@ impl<'a> SymbolName<'a> {
     /// demangling.
     pub fn new(bytes: &'a [u8]) -> SymbolName<'a> {
         SymbolName {
-            bytes: bytes,
+            bytes,
             #[cfg(feature = "backtrace")]
-            demangled: UnsafeCell::new(None),
+            demangled: OnceLock::new(),
         }
     }
 
@@ -45,36 +64,287 @@ impl<'a> SymbolName<'a> {
     pub fn demangled(&self) -> Option<&str> {
         #[cfg(feature = "backtrace")]
         {
-            let demangled = self.demangled.get();
-            unsafe {
-                if (*demangled).is_none() {
-                    (*demangled) = Some({
-                        let mut sym = backtrace_support::SymbolName::new(self.bytes).to_string();
-                        // chop off the hash marker for rust functions
-                        let truncate = {
-                            let mut iter = sym.rsplitn(2, "::");
-                            let chop_last = iter.next()
-                                .map_or(false, |h| h.len() == 17 && h.starts_with("h"));
-                            if chop_last {
-                                Some(iter.next().unwrap_or("").len())
-                            } else {
-                                None
-                            }
-                        };
-                        if let Some(truncate) = truncate {
-                            sym.truncate(truncate);
-                        }
-                        sym
-                    });
-                }
-                (*demangled).as_ref().map(|x| &x[..])
-            }
+            Some(self.demangled.get_or_init(|| demangle_shared(self.bytes)))
         }
         #[cfg(not(feature = "backtrace"))]
         {
             None
         }
     }
+
+    /// Returns `true` if the raw name is a mangled rust symbol.
+    ///
+    /// This detects both the legacy mangling (`_ZN...17h<hash>E`) as well as
+    /// the v0 mangling scheme (`_R...`).
+    pub fn is_rust(&self) -> bool {
+        is_legacy_rust_symbol(self.bytes) || is_v0_rust_symbol(self.bytes)
+    }
+
+    /// Returns `true` if demangling produced a name different from the raw one.
+    pub fn was_demangled(&self) -> bool {
+        match self.demangled() {
+            Some(demangled) => self.as_str() != Some(demangled),
+            None => false,
+        }
+    }
+
+    /// Returns the demangled name with all generic arguments removed.
+    ///
+    /// This turns `alloc::vec::Vec<u8>::push` and `foo::<T>` into
+    /// `alloc::vec::Vec::push` and `foo` which is useful to group functions
+    /// regardless of their instantiation.  Qualified paths like
+    /// `<Foo<T> as Bar>::baz` or `core::cmp::<impl Ord for i32>::cmp` keep
+    /// their brackets but lose the arguments within.  If the name cannot be
+    /// demangled the raw name is used.
+    pub fn without_generics(&self) -> Option<String> {
+        let name = self.demangled().or_else(|| self.as_str())?;
+        Some(strip_generics(name))
+    }
+
+    /// Returns a value that displays the raw symbol name without demangling.
+    ///
+    /// This is useful for passing names to external tools such as addr2line.
+    pub fn raw_display(&self) -> impl fmt::Display + 'a {
+        RawSymbolName(self.bytes)
+    }
+}
+
+struct RawSymbolName<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for RawSymbolName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&String::from_utf8_lossy(self.0), f)
+    }
+}
+
+/// Demangles a raw symbol name.
+#[cfg(feature = "backtrace")]
+fn demangle(bytes: &[u8]) -> String {
+    let sym = backtrace_support::SymbolName::new(bytes);
+    // v0 mangled names carry no trailing hash but their crate
+    // disambiguators are omitted by the alternate form
+    if !is_legacy_rust_symbol(bytes) {
+        return format!("{:#}", sym);
+    }
+    // chop off the hash marker for rust functions
+    let mut sym = sym.to_string();
+    let truncate = {
+        let mut iter = sym.rsplitn(2, "::");
+        let chop_last = iter
+            .next()
+            .is_some_and(|h| h.len() == 17 && h.starts_with('h'));
+        if chop_last {
+            Some(iter.next().unwrap_or("").len())
+        } else {
+            None
+        }
+    };
+    if let Some(truncate) = truncate {
+        sym.truncate(truncate);
+    }
+    sym
+}
+
+/// The most names the demangling cache holds.
+#[cfg(all(feature = "backtrace", feature = "intern"))]
+const MAX_INTERNED_NAMES: usize = 16384;
+
+/// Maps raw symbol names to their demangled form.
+#[cfg(all(feature = "backtrace", feature = "intern"))]
+type InternedNames = Mutex<HashMap<Box<[u8]>, Arc<str>>>;
+
+/// Demangles a raw symbol name into a shared string.
+///
+/// With the `intern` feature demangled names are cached by their raw bytes
+/// so that demangling the same name again returns the same allocation.
+#[cfg(feature = "backtrace")]
+fn demangle_shared(bytes: &[u8]) -> Arc<str> {
+    #[cfg(feature = "intern")]
+    {
+        static INTERNED_NAMES: OnceLock<InternedNames> = OnceLock::new();
+        let mut cache = INTERNED_NAMES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|x| x.into_inner());
+        if let Some(name) = cache.get(bytes) {
+            return name.clone();
+        }
+        let name: Arc<str> = Arc::from(demangle(bytes));
+        if cache.len() < MAX_INTERNED_NAMES {
+            cache.insert(bytes.into(), name.clone());
+        }
+        name
+    }
+    #[cfg(not(feature = "intern"))]
+    {
+        Arc::from(demangle(bytes))
+    }
+}
+
+/// The most paths the filename cache holds.
+const MAX_INTERNED_PATHS: usize = 4096;
+
+/// Returns a shared copy of a filename.
+///
+/// Symbols of the same source file share a single allocation of its path
+/// as long as the cache has room.
+fn intern_path(path: &Path) -> Arc<Path> {
+    static INTERNED_PATHS: OnceLock<Mutex<HashSet<Arc<Path>>>> = OnceLock::new();
+    let mut cache = INTERNED_PATHS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|x| x.into_inner());
+    if let Some(rv) = cache.get(path) {
+        return rv.clone();
+    }
+    let rv: Arc<Path> = Arc::from(path);
+    if cache.len() < MAX_INTERNED_PATHS {
+        cache.insert(rv.clone());
+    }
+    rv
+}
+
+/// Checks for a legacy mangled rust symbol with its trailing hash.
+fn is_legacy_rust_symbol(bytes: &[u8]) -> bool {
+    let inner = match bytes
+        .strip_prefix(b"__ZN")
+        .or_else(|| bytes.strip_prefix(b"_ZN"))
+        .and_then(|x| x.strip_suffix(b"E"))
+    {
+        Some(inner) => inner,
+        None => return false,
+    };
+    inner.len() >= 19 && {
+        let (rest, hash) = inner.split_at(inner.len() - 19);
+        !rest.is_empty() && hash.starts_with(b"17h") && hash[3..].iter().all(u8::is_ascii_hexdigit)
+    }
+}
+
+/// Checks for a v0 mangled rust symbol.
+fn is_v0_rust_symbol(bytes: &[u8]) -> bool {
+    bytes
+        .strip_prefix(b"__R")
+        .or_else(|| bytes.strip_prefix(b"_R"))
+        .and_then(|x| x.first())
+        .is_some_and(|x| x.is_ascii_uppercase() || x.is_ascii_digit())
+}
+
+/// Returns the name of the crate a v0 mangled symbol belongs to.
+///
+/// For impl blocks this is the crate containing the impl.  Names that start
+/// with a back reference or a non path type are not understood.
+fn v0_crate_name(bytes: &[u8]) -> Option<&[u8]> {
+    fn skip_disambiguator(bytes: &[u8]) -> Option<&[u8]> {
+        match bytes.strip_prefix(b"s") {
+            Some(rest) => rest.iter().position(|&x| x == b'_').map(|x| &rest[x + 1..]),
+            None => Some(bytes),
+        }
+    }
+
+    if !is_v0_rust_symbol(bytes) {
+        return None;
+    }
+    let mut rest = bytes
+        .strip_prefix(b"__R")
+        .or_else(|| bytes.strip_prefix(b"_R"))?;
+    // skip the optional encoding version
+    while rest.first()?.is_ascii_digit() {
+        rest = &rest[1..];
+    }
+    loop {
+        match *rest.first()? {
+            b'N' => rest = rest.get(2..)?,
+            b'M' | b'X' => rest = skip_disambiguator(&rest[1..])?,
+            b'Y' | b'I' => rest = &rest[1..],
+            b'C' => {
+                rest = skip_disambiguator(&rest[1..])?;
+                let digits = rest.iter().take_while(|x| x.is_ascii_digit()).count();
+                let len: usize = str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+                rest = &rest[digits..];
+                rest = rest.strip_prefix(b"_").unwrap_or(rest);
+                return rest.get(..len);
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Removes balanced `<...>` groups from a demangled name.
+///
+/// Groups that are qualified paths, at the start of the name or as a path
+/// segment like `<impl Trait for Type>`, only get their contents stripped.
+/// Unbalanced names are left alone from that point on.
+fn strip_generics(name: &str) -> String {
+    let mut rv = String::with_capacity(name.len());
+    let mut idx = 0;
+    while let Some(offset) = name[idx..].find('<') {
+        let open = idx + offset;
+        let close = match find_closing_bracket(&name[open..]) {
+            Some(close) => open + close,
+            None => break,
+        };
+        rv.push_str(&name[idx..open]);
+        let inner = &name[open + 1..close];
+        if open == 0 || (rv.ends_with("::") && is_qualified_path(inner)) {
+            rv.push('<');
+            rv.push_str(&strip_generics(inner));
+            rv.push('>');
+        } else if rv.ends_with("::") {
+            // turbofish
+            rv.truncate(rv.len() - 2);
+        }
+        idx = close + 1;
+    }
+    rv.push_str(&name[idx..]);
+    rv
+}
+
+/// Checks if the contents of a `<...>` group are a qualified path.
+///
+/// These are `impl Trait for Type` and `Type as Trait` as opposed to the
+/// generic arguments of a turbofish.
+fn is_qualified_path(inner: &str) -> bool {
+    if inner.starts_with("impl ") {
+        return true;
+    }
+    let bytes = inner.as_bytes();
+    let mut depth = 0usize;
+    for (idx, &c) in bytes.iter().enumerate() {
+        match c {
+            b'<' => depth += 1,
+            b'>' if idx > 0 && bytes[idx - 1] == b'-' => {}
+            b'>' => depth = depth.saturating_sub(1),
+            b' ' if depth == 0 && inner[idx..].starts_with(" as ") => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns the index of the `>` matching the `<` the string starts with.
+///
+/// The `>` of `->` in function signatures does not count.
+fn find_closing_bracket(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    for (idx, &c) in bytes.iter().enumerate() {
+        match c {
+            b'<' => depth += 1,
+            b'>' if idx > 0 && bytes[idx - 1] == b'-' => {}
+            b'>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|x| x == needle)
 }
 
 impl<'a> fmt::Display for SymbolName<'a> {
@@ -101,20 +371,96 @@ impl<'a> fmt::Debug for SymbolName<'a> {
     }
 }
 
+impl<'a> PartialEq for SymbolName<'a> {
+    fn eq(&self, other: &SymbolName<'a>) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<'a> Eq for SymbolName<'a> {}
+
+impl<'a> Hash for SymbolName<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+    }
+}
+
 /// Represents a symbol in a frame.
+///
+/// Symbols compare and hash by their name, filename and line number.  The
+/// address is ignored as it differs between runs.
+#[derive(Clone)]
 pub struct Symbol {
     name: Option<Vec<u8>>,
     addr: Option<usize>,
-    filename: Option<PathBuf>,
+    filename: Option<Arc<Path>>,
     lineno: Option<u32>,
+    column: Option<u32>,
 }
 
 impl Symbol {
+    /// Creates a new symbol.
+    ///
+    /// This is useful for synthetic backtraces or to carry the results of
+    /// offline symbolication.  The column can be set with `with_column`.
+    pub fn new(
+        name: Option<Vec<u8>>,
+        addr: Option<usize>,
+        filename: Option<PathBuf>,
+        lineno: Option<u32>,
+    ) -> Symbol {
+        Symbol {
+            name,
+            addr,
+            filename: filename.map(|x| intern_path(&x)),
+            lineno,
+            column: None,
+        }
+    }
+
+    /// Sets the column number of the symbol.
+    pub fn with_column(mut self, column: u32) -> Symbol {
+        self.column = Some(column);
+        self
+    }
+
+    /// Sets the name of the symbol from UTF-16.
+    ///
+    /// Windows APIs report names as wide strings.  These are stored as
+    /// utf-8 so that `SymbolName::as_str` and demangling work on them,
+    /// unpaired surrogates are replaced.
+    pub fn with_wide_name(mut self, name: &[u16]) -> Symbol {
+        self.name = Some(String::from_utf16_lossy(name).into_bytes());
+        self
+    }
+
     /// Returns the name of the symbol
-    pub fn name(&self) -> Option<SymbolName> {
+    pub fn name(&self) -> Option<SymbolName<'_>> {
         self.name.as_ref().map(|s| SymbolName::new(s))
     }
 
+    /// Returns the raw name of the symbol as an owned string.
+    ///
+    /// No demangling is performed and invalid utf-8 is replaced, which
+    /// makes this suitable for external tools such as addr2line.
+    pub fn mangled_name(&self) -> Option<String> {
+        self.name
+            .as_ref()
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+    }
+
+    /// Returns the demangled name of the symbol as an owned string.
+    ///
+    /// If the name cannot be demangled the raw name is returned with invalid
+    /// utf-8 replaced.
+    pub fn demangled_name(&self) -> Option<String> {
+        let name = self.name()?;
+        Some(match name.demangled() {
+            Some(demangled) => demangled.to_string(),
+            None => String::from_utf8_lossy(name.as_bytes()).into_owned(),
+        })
+    }
+
     /// Returns the address of the symbol
     pub fn addr(&self) -> Option<*mut c_void> {
         self.addr.map(|s| s as *mut c_void)
@@ -122,7 +468,18 @@ impl Symbol {
 
     /// Returns the filename
     pub fn filename(&self) -> Option<&Path> {
-        self.filename.as_ref().map(|p| &**p)
+        self.filename.as_deref()
+    }
+
+    /// Returns the filename relative to the given base path.
+    ///
+    /// Returns `None` if there is no filename or it is not located
+    /// within `base`.
+    pub fn relative_path(&self, base: &Path) -> Option<PathBuf> {
+        self.filename()?
+            .strip_prefix(base)
+            .ok()
+            .map(|x| x.to_path_buf())
     }
 
     /// Returns the line number
@@ -130,21 +487,93 @@ impl Symbol {
         self.lineno
     }
 
+    /// Returns the column number
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+
+    /// The parts of the symbol that identify it independently of addresses.
+    fn identity(&self) -> (Option<&[u8]>, Option<&Path>, Option<u32>) {
+        (self.name.as_deref(), self.filename(), self.lineno)
+    }
+
+    /// Returns `true` if the symbol belongs to the `std` crate.
+    pub fn is_std(&self) -> bool {
+        self.is_in_crate("std")
+    }
+
+    /// Returns `true` if the symbol belongs to the `core` crate.
+    pub fn is_core(&self) -> bool {
+        self.is_in_crate("core")
+    }
+
+    /// Returns `true` if the symbol belongs to the standard library.
+    ///
+    /// This covers the `std`, `core` and `alloc` crates.
+    pub fn is_runtime(&self) -> bool {
+        self.is_std() || self.is_core() || self.is_in_crate("alloc")
+    }
+
+    /// Returns `true` if the symbol's path starts with the given crate.
+    ///
+    /// For trait implementations (`<Type as Trait>::method`) the crate of
+    /// the type is checked.
+    fn is_in_crate(&self, krate: &str) -> bool {
+        let name = match self.name() {
+            Some(name) => name,
+            None => return false,
+        };
+        if v0_crate_name(name.as_bytes()) == Some(krate.as_bytes()) {
+            return true;
+        }
+        let demangled = match name.demangled().or_else(|| name.as_str()) {
+            Some(demangled) => demangled,
+            None => return false,
+        };
+        let path = demangled.strip_prefix('<').unwrap_or(demangled);
+        path.strip_prefix(krate)
+            .is_some_and(|rest| rest.starts_with("::"))
+    }
+
+    /// Returns `true` if the demangled or raw name starts with one of the prefixes.
+    fn has_name_prefix(&self, prefixes: &[String]) -> bool {
+        let name = match self.name() {
+            Some(name) => name,
+            None => return false,
+        };
+        prefixes.iter().any(|prefix| {
+            name.demangled().is_some_and(|x| x.starts_with(&prefix[..]))
+                || name.as_str().is_some_and(|x| x.starts_with(&prefix[..]))
+        })
+    }
+
     /// Returns `true` if this is an internal symbol.
-    fn is_backtrace_internal(&self) -> bool {
+    fn is_backtrace_internal(&self, options: &TrimOptions) -> bool {
+        if self.has_name_prefix(&options.internal_prefixes) {
+            return true;
+        }
+
         let name = match self.name() {
             Some(name) => name,
             None => return false,
         };
 
         if let Some(raw_name) = name.as_str() {
-            if raw_name.starts_with("__ZN6defeat") || raw_name.starts_with("defeat::") {
+            if raw_name.starts_with("__ZN6defeat")
+                || raw_name.starts_with("_ZN6defeat")
+                || raw_name.starts_with("defeat::")
+            {
                 return true;
             }
         }
 
+        if v0_crate_name(name.as_bytes()) == Some(&b"defeat"[..]) {
+            return true;
+        }
+
         if let Some(name) = name.demangled() {
-            if name.starts_with("defeat::") {
+            // v0 demangling renders methods as `<defeat::Type>::method`
+            if name.starts_with("defeat::") || name.starts_with("<defeat::") {
                 return true;
             }
         }
@@ -153,7 +582,11 @@ impl Symbol {
     }
 
     /// Returns `true` if this is the border frame leaving rust user code.
-    fn is_end_of_user_code(&self) -> bool {
+    fn is_end_of_user_code(&self, options: &TrimOptions) -> bool {
+        if self.has_name_prefix(&options.boundary_prefixes) {
+            return true;
+        }
+
         let name = match self.name() {
             Some(name) => name,
             None => return false,
@@ -161,12 +594,19 @@ impl Symbol {
 
         if let Some(raw_name) = name.as_str() {
             if raw_name.starts_with("__ZN3std2rt10lang_start")
+                || raw_name.starts_with("_ZN3std2rt10lang_start")
                 || raw_name.starts_with("std::rt::lang_start::")
             {
                 return true;
             }
         }
 
+        if v0_crate_name(name.as_bytes()) == Some(&b"std"[..])
+            && contains_bytes(name.as_bytes(), b"3std2rt10lang_start")
+        {
+            return true;
+        }
+
         if let Some(name) = name.demangled() {
             if name.starts_with("std::rt::lang_start::") {
                 return true;
@@ -177,69 +617,357 @@ impl Symbol {
     }
 }
 
+/// Options to customize how a backtrace is trimmed.
+///
+/// The default options trim the same frames as `Backtrace::trimmed()`.
+#[derive(Debug, Clone, Default)]
+pub struct TrimOptions {
+    internal_prefixes: Vec<String>,
+    boundary_prefixes: Vec<String>,
+    keep_unresolved: bool,
+}
+
+impl TrimOptions {
+    /// Creates the default trim options.
+    pub fn new() -> TrimOptions {
+        Default::default()
+    }
+
+    /// Adds a symbol prefix that marks frames as internal.
+    ///
+    /// Internal frames at the top of the backtrace are stripped.  This is
+    /// useful to hide helper functions that construct errors.
+    pub fn internal_prefix<S: Into<String>>(mut self, prefix: S) -> TrimOptions {
+        self.internal_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Adds a symbol prefix that marks the end of user code.
+    ///
+    /// The first frame matching a boundary prefix and all frames below it
+    /// are stripped.  `std::rt::lang_start` is always a boundary.
+    pub fn boundary_prefix<S: Into<String>>(mut self, prefix: S) -> TrimOptions {
+        self.boundary_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Controls whether frames without symbol information are kept.
+    ///
+    /// A frame counts as unresolved if it has no symbols or none of its
+    /// symbols has a name or a filename.  Such frames are dropped by default
+    /// as they only show up as `in ?`.
+    pub fn keep_unresolved(mut self, yes: bool) -> TrimOptions {
+        self.keep_unresolved = yes;
+        self
+    }
+}
+
 /// The reason why a backtrace is captured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CapturePurpose {
     /// Capture a backtrace for a panic.
     Panic,
     /// Capture a backtrace for an error.
     Error,
+    /// Capture a backtrace controlled by a custom environment variable.
+    ///
+    /// If the variable is not set `RUST_BACKTRACE` is consulted instead.
+    Env(&'static str),
+}
+
+/// The state of symbol resolution of a frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResolutionState {
+    /// The symbols were not looked up yet.
+    Unresolved,
+    /// The symbols were looked up and at least one was found.
+    Resolved,
+    /// The symbols were looked up but none were found.
+    ///
+    /// This is typically the case for stripped binaries or addresses
+    /// outside of any loaded module.
+    ResolvedEmpty,
 }
 
 /// A hint to what type of IP is stored in a frame.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum AddrHint {
     /// A precise address
     Precise,
     /// A return address
     Return,
+    /// It is not known if the address is precise or a return address.
+    ///
+    /// This is treated like a return address for symbolication which is the
+    /// safer assumption for frames reconstructed from external data.
+    Unknown,
 }
 
 /// Represents a frame.
+///
+/// Frames resolve their symbols lazily on first access.  The resolution
+/// is synchronized so frames (and backtraces) can be shared across threads.
+#[derive(Clone)]
 pub struct Frame {
-    ip: *mut c_void,
+    ip: usize,
     hint: AddrHint,
-    #[cfg(feature = "backtrace")]
-    resolved: UnsafeCell<Option<Vec<Symbol>>>,
+    resolved: OnceLock<Vec<Symbol>>,
+    module: OnceLock<Option<Module>>,
+    module_base_addr: Option<usize>,
+    symbol_addr: Option<usize>,
+    repeat: usize,
+}
+
+/// The module (executable or shared object) a frame is located in.
+#[derive(Clone)]
+struct Module {
+    base: usize,
+    name: Option<PathBuf>,
 }
 
+/// How far to step back from a return address to land on the call.
+const RETURN_ADDR_ADJUSTMENT: usize = if cfg!(any(
+    target_arch = "aarch64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "mips",
+    target_arch = "mips64",
+    target_arch = "sparc64"
+)) {
+    4
+} else {
+    1
+};
+
 #[cfg(feature = "backtrace")]
 fn resolve_frame(ip: *mut c_void) -> Vec<Symbol> {
     let mut rv = Vec::with_capacity(1);
+    if ip.is_null() {
+        return rv;
+    }
+    // the backtrace crate assumes return addresses and steps back by one
+    // byte.  `ip` is already the address to look up so compensate for it.
+    let lookup = ip as usize;
+    let ip = lookup.wrapping_add(1) as *mut c_void;
     backtrace_support::resolve(ip, |symbol| {
         rv.push(Symbol {
             name: symbol.name().map(|m| m.as_bytes().to_vec()),
-            addr: symbol.addr().map(|a| a as usize),
-            filename: symbol.filename().map(|m| m.to_path_buf()),
+            // some resolvers report the looked up address instead of the
+            // start of the symbol which is not useful
+            addr: symbol.addr().map(|a| a as usize).filter(|&a| a != lookup),
+            filename: symbol.filename().map(intern_path),
             lineno: symbol.lineno(),
+            column: symbol.colno(),
         });
     });
     rv
 }
 
+#[cfg(not(feature = "backtrace"))]
+fn resolve_frame(ip: *mut c_void) -> Vec<Symbol> {
+    let _ = ip;
+    Vec::new()
+}
+
+/// Captures backtraces if `defeat` cannot unwind the stack itself.
+///
+/// Without the `backtrace` feature all capture functions consult the
+/// strategy installed with `set_backtrace_strategy` instead, for instance to
+/// capture from a platform API or to record a single `#[track_caller]`
+/// location.  With the feature enabled the strategy is never used.
+pub trait BacktraceStrategy: Send + Sync {
+    /// Captures a backtrace at the current position.
+    fn capture(&self) -> Option<Backtrace>;
+}
+
+static BACKTRACE_STRATEGY: RwLock<Option<Arc<dyn BacktraceStrategy>>> = RwLock::new(None);
+
+/// Installs the strategy used to capture backtraces.
+///
+/// This replaces any previously installed strategy.  See
+/// `BacktraceStrategy` for when it is used.
+pub fn set_backtrace_strategy<S: BacktraceStrategy + 'static>(strategy: S) {
+    *BACKTRACE_STRATEGY
+        .write()
+        .unwrap_or_else(|x| x.into_inner()) = Some(Arc::new(strategy));
+}
+
+/// Returns the installed strategy.
+///
+/// The lock is released before the strategy runs so it may capture or
+/// install another strategy itself.
+fn current_strategy() -> Option<Arc<dyn BacktraceStrategy>> {
+    BACKTRACE_STRATEGY
+        .read()
+        .unwrap_or_else(|x| x.into_inner())
+        .clone()
+}
+
+/// Captures with the installed strategy, applying skips and frame limits.
+#[cfg(not(feature = "backtrace"))]
+fn capture_with_strategy(skip: usize, max_frames: usize) -> Option<Backtrace> {
+    let os_error = last_os_error();
+    let mut bt = current_strategy()?.capture()?.with_current_thread();
+    let info = bt.info_mut();
+    if info.os_error.is_none() {
+        info.os_error = os_error;
+    }
+    let max_frames = max_frames.min(max_backtrace_frames());
+    if let BacktraceRepr::Frames(ref mut frames) = bt.repr {
+        frames.drain(..skip.min(frames.len()));
+        if frames.len() > max_frames {
+            frames.truncate(max_frames);
+            bt.truncated = true;
+        }
+    }
+    Some(bt)
+}
+
+/// A region of a module mapped into the process.
+#[cfg(target_os = "linux")]
+struct Mapping {
+    start: usize,
+    end: usize,
+    module: Module,
+}
+
+/// The memory maps of the process as of the last module lookup.
+#[cfg(target_os = "linux")]
+static MODULE_MAPPINGS: RwLock<Vec<Mapping>> = RwLock::new(Vec::new());
+
+/// Parses the memory maps of the process.
+#[cfg(target_os = "linux")]
+fn read_mappings() -> Vec<Mapping> {
+    let maps = match std::fs::read_to_string("/proc/self/maps") {
+        Ok(maps) => maps,
+        Err(_) => return vec![],
+    };
+    // lines look like `start-end perms offset dev inode path`
+    let regions: Vec<_> = maps
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let (start, end) = parts.next()?.split_once('-')?;
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+            let path = parts.nth(4)?;
+            Some((start, end, path))
+        })
+        .collect();
+    // the module is loaded at the lowest mapping of its file
+    let mut bases = HashMap::new();
+    for &(start, _, path) in &regions {
+        let base = bases.entry(path).or_insert(start);
+        *base = (*base).min(start);
+    }
+    regions
+        .iter()
+        .map(|&(start, end, path)| Mapping {
+            start,
+            end,
+            module: Module {
+                base: bases[path],
+                name: if path.starts_with('/') {
+                    Some(PathBuf::from(path))
+                } else {
+                    None
+                },
+            },
+        })
+        .collect()
+}
+
+/// Finds the module containing `ip` in the memory maps of the process.
+///
+/// The maps are cached and only read again if `ip` lies outside of all
+/// known mappings, for instance because a library was loaded since.
+#[cfg(target_os = "linux")]
+fn lookup_module(ip: usize) -> Option<Module> {
+    fn find(mappings: &[Mapping], ip: usize) -> Option<Module> {
+        mappings
+            .iter()
+            .find(|x| x.start <= ip && ip < x.end)
+            .map(|x| x.module.clone())
+    }
+
+    // the outermost frame often has a null ip, never reload for it
+    if ip == 0 {
+        return None;
+    }
+    let mappings = MODULE_MAPPINGS.read().unwrap_or_else(|x| x.into_inner());
+    if let Some(rv) = find(&mappings, ip) {
+        return Some(rv);
+    }
+    drop(mappings);
+    let mut mappings = MODULE_MAPPINGS.write().unwrap_or_else(|x| x.into_inner());
+    *mappings = read_mappings();
+    find(&mappings, ip)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lookup_module(ip: usize) -> Option<Module> {
+    let _ = ip;
+    None
+}
+
 impl Frame {
     /// Creates a new frame.
     pub fn new(ip: *mut c_void, addr_hint: AddrHint) -> Frame {
         Frame {
-            ip: ip,
+            ip: ip as usize,
             hint: addr_hint,
-            #[cfg(feature = "backtrace")]
-            resolved: UnsafeCell::new(None),
+            resolved: OnceLock::new(),
+            module: OnceLock::new(),
+            module_base_addr: None,
+            symbol_addr: None,
+            repeat: 1,
         }
     }
 
-    /// Creates a new resolved frame.
+    /// Creates a new frame from a frame of the unwinder.
     #[cfg(feature = "backtrace")]
-    fn new_resolved(ip: *mut c_void, addr_hint: AddrHint, symbols: Vec<Symbol>) -> Frame {
+    fn from_traced(frame: &backtrace_support::Frame, addr_hint: AddrHint) -> Frame {
+        let mut rv = Frame::new(frame.ip(), addr_hint);
+        if !frame.symbol_address().is_null() && frame.symbol_address() != frame.ip() {
+            rv.symbol_addr = Some(frame.symbol_address() as usize);
+        }
+        rv.module_base_addr = frame.module_base_address().map(|x| x as usize);
+        rv
+    }
+
+    /// Creates a frame for a source location, see `Backtrace::from_location`.
+    fn from_location(loc: &'static Location<'static>) -> Frame {
+        let symbol = Symbol::new(
+            None,
+            None,
+            Some(PathBuf::from(loc.file())),
+            Some(loc.line()),
+        )
+        .with_column(loc.column());
+        Frame::new_resolved(ptr::null_mut(), AddrHint::Precise, vec![symbol])
+    }
+
+    /// Creates a new frame with already resolved symbols.
+    ///
+    /// The symbols are used as they are and the frame is never resolved
+    /// again.
+    pub fn new_resolved(ip: *mut c_void, addr_hint: AddrHint, symbols: Vec<Symbol>) -> Frame {
         Frame {
-            ip: ip,
+            ip: ip as usize,
             hint: addr_hint,
-            resolved: UnsafeCell::new(Some(symbols)),
+            resolved: OnceLock::from(symbols),
+            module: OnceLock::new(),
+            module_base_addr: None,
+            symbol_addr: None,
+            repeat: 1,
         }
     }
 
     /// The instruction pointer of this frame.
     pub fn ip(&self) -> *mut c_void {
-        self.ip
+        self.ip as *mut c_void
     }
 
     /// The address hint.
@@ -249,123 +977,719 @@ impl Frame {
 
     /// The address of the call.
     ///
-    /// If the frame address hint is `Return` this will attempt to unwind back
-    /// to the location of the call.
+    /// If the frame address hint is `Return` or `Unknown` this will attempt
+    /// to unwind back to the location of the call.  On architectures with
+    /// fixed width instructions this points to the call instruction itself,
+    /// otherwise it points into it which is good enough for symbolication.
     pub fn call_ip(&self) -> *mut c_void {
         match self.hint {
-            AddrHint::Precise => self.ip,
-            AddrHint::Return => {
-                // XXX: unsafe, stupid and wrong
-                unsafe { self.ip.offset(-1) }
+            AddrHint::Precise => self.ip(),
+            AddrHint::Return | AddrHint::Unknown => {
+                self.ip
+                    .checked_sub(RETURN_ADDR_ADJUSTMENT)
+                    .unwrap_or(self.ip) as *mut c_void
             }
         }
     }
 
+    /// Checks if two frames point at the same location.
+    ///
+    /// Frames are at the same location if their `call_ip()` matches, which
+    /// normalizes differences in the address hint.  If the symbols of both
+    /// frames are already resolved, the file and line of their first symbols
+    /// have to match as well.
+    pub fn same_location(&self, other: &Frame) -> bool {
+        if self.call_ip() != other.call_ip() {
+            return false;
+        }
+        match (self.resolved_location(), other.resolved_location()) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+    }
+
+    /// The file and line of the frame.
+    ///
+    /// This is the location of the last symbol, which for frames with
+    /// inlined functions is the outermost call: the function the code was
+    /// inlined into.
+    pub fn location(&self) -> Option<(PathBuf, Option<u32>)> {
+        let symbol = self.symbols().last()?;
+        Some((symbol.filename()?.to_path_buf(), symbol.lineno()))
+    }
+
+    /// The location of the frame formatted as `file:line`.
+    ///
+    /// The line is left out if it is not known.  This is the format most
+    /// editors and IDEs accept for links.
+    pub fn location_string(&self) -> Option<String> {
+        Some(match self.location()? {
+            (file, Some(lineno)) => format!("{}:{}", file.display(), lineno),
+            (file, None) => file.display().to_string(),
+        })
+    }
+
+    /// The file and line of the first symbol if already resolved.
+    fn resolved_location(&self) -> Option<(&Path, u32)> {
+        let symbol = self.resolved.get()?.first()?;
+        Some((symbol.filename()?, symbol.lineno()?))
+    }
+
     /// The symbols corresponding with this frame.
     ///
     /// If the symbols are not known this might be an empty list.
-    #[cfg(feature = "backtrace")]
     pub fn symbols(&self) -> &[Symbol] {
-        #[cfg(feature = "backtrace")]
-        {
-            let resolved = self.resolved.get();
-            unsafe {
-                if (*resolved).is_none() {
-                    (*resolved) = Some(resolve_frame(self.call_ip()));
-                }
-                &(*resolved).as_ref().unwrap()[..]
+        &self.resolved.get_or_init(|| {
+            let mut symbols = resolve_frame(self.call_ip());
+            // the unwinder knows where the function owning the frame starts
+            if let (Some(addr), Some(sym)) = (self.symbol_addr, symbols.last_mut()) {
+                sym.addr = Some(addr);
             }
+            symbols
+        })[..]
+    }
+
+    /// How many consecutive frames this frame stands for.
+    ///
+    /// This is `1` unless the frame was produced by
+    /// `Backtrace::collapse_recursion`.
+    pub fn repeat_count(&self) -> usize {
+        self.repeat
+    }
+
+    /// Resolves the symbols of the frame with a custom resolver.
+    ///
+    /// The resolver is called with `call_ip` and its symbols are cached as
+    /// if the frame had been resolved normally, which is useful for
+    /// addresses the built-in symbolication cannot handle (for instance of
+    /// an embedded runtime).  If the frame is already resolved the resolver
+    /// is not called.  Returns the symbols of the frame.
+    pub fn resolve_with<F: FnOnce(*mut c_void) -> Vec<Symbol>>(&self, resolver: F) -> &[Symbol] {
+        &self.resolved.get_or_init(|| resolver(self.call_ip()))[..]
+    }
+
+    /// Returns the state of symbol resolution without resolving.
+    ///
+    /// This tells apart a frame that has no symbols because nothing was
+    /// found from one that was not resolved yet.
+    pub fn resolution_state(&self) -> ResolutionState {
+        match self.resolved.get() {
+            None => ResolutionState::Unresolved,
+            Some(symbols) if symbols.is_empty() => ResolutionState::ResolvedEmpty,
+            Some(_) => ResolutionState::Resolved,
         }
-        #[cfg(not(feature = "backtrace"))]
-        {
-            &[]
-        }
+    }
+
+    /// The number of symbols that were inlined into this frame.
+    ///
+    /// A resolved frame lists inlined functions before the function that
+    /// physically owns the frame, so all but the last symbol are inlined.
+    pub fn inline_count(&self) -> usize {
+        self.symbols().len().saturating_sub(1)
+    }
+
+    /// Returns the best display name of the frame.
+    ///
+    /// This is the demangled name of the first symbol of the frame, falling
+    /// back to its raw name if it cannot be demangled.
+    pub fn symbol_name(&self) -> Option<String> {
+        self.symbols().first()?.demangled_name()
+    }
+
+    /// The offset of the call into the function owning the frame.
+    ///
+    /// This is the distance of `call_ip` from the start address of the
+    /// outermost symbol, as commonly shown as `function+0x2a`.  It is `None`
+    /// if the symbol has no address or the address lies after the call.
+    pub fn offset_in_symbol(&self) -> Option<usize> {
+        let addr = self.symbols().last()?.addr()? as usize;
+        (self.call_ip() as usize).checked_sub(addr)
+    }
+
+    /// The load address of the module this frame is located in.
+    ///
+    /// Together with `module_name` this allows symbolicating frames offline
+    /// as `ip - module_base` is the offset into the module.  Where the
+    /// unwinder does not report the module it is looked up lazily, which is
+    /// currently only supported on Linux.  The memory maps read for this are
+    /// cached across frames and backtraces.
+    pub fn module_base(&self) -> Option<*mut c_void> {
+        self.module().map(|x| x.base as *mut c_void)
+    }
+
+    /// The path of the module this frame is located in.
+    pub fn module_name(&self) -> Option<&Path> {
+        self.module().and_then(|x| x.name.as_deref())
+    }
+
+    fn module(&self) -> Option<&Module> {
+        self.module
+            .get_or_init(|| {
+                // the base reported by the unwinder wins, the name still
+                // has to come from the module mappings.
+                let module = lookup_module(self.ip);
+                match self.module_base_addr {
+                    Some(base) => Some(Module {
+                        base,
+                        name: module.and_then(|x| x.name),
+                    }),
+                    None => module,
+                }
+            })
+            .as_ref()
     }
 
     /// Releases the symbols
-    fn take_symbols(&self) -> Vec<Symbol> {
+    fn take_symbols(self) -> Vec<Symbol> {
         self.symbols();
-        unsafe { (*self.resolved.get()).take().unwrap() }
+        self.resolved.into_inner().unwrap()
     }
 }
 
+#[derive(Clone)]
 enum BacktraceRepr {
     /// a backtrace that is always empty
     Empty,
     /// A backtrace made from frames.
-    #[cfg(feature = "backtrace")]
     Frames(Vec<Frame>),
 }
 
 /// Represents a backtrace.
+///
+/// When displayed the precision limits the number of frames shown, for
+/// instance `{:.5}` prints the five most recent frames.  The alternate
+/// form (`{:#}`) adds instruction pointers and aligns the locations.
+///
+/// The debug representation only shows the number of frames to keep
+/// assertion failures readable, use `{:#?}` to list all of them.
+#[derive(Clone)]
 pub struct Backtrace {
     repr: BacktraceRepr,
+    truncated: bool,
+    full: bool,
+    purpose: Option<CapturePurpose>,
+    info: Option<Box<CaptureInfo>>,
 }
 
-impl Backtrace {
+/// Details about the circumstances of a capture.
+///
+/// These are boxed to keep backtraces (and errors holding them) small.
+#[derive(Clone, Default)]
+struct CaptureInfo {
+    caller: Option<&'static Location<'static>>,
+    thread_name: Option<String>,
+    thread_id: Option<ThreadId>,
+    os_error: Option<i32>,
+}
+
+impl Backtrace {
     /// Produces an empty backtrace.
     pub fn empty() -> Backtrace {
         Default::default()
     }
 
+    /// Creates a backtrace from a list of frames.
+    ///
+    /// The frames are expected to be ordered with the most recent call first.
+    pub fn from_frames(frames: Vec<Frame>) -> Backtrace {
+        Backtrace {
+            repr: BacktraceRepr::Frames(frames),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a backtrace for frames captured on the current thread.
+    #[cfg(feature = "backtrace")]
+    fn captured(frames: Vec<Frame>, truncated: bool, os_error: Option<i32>) -> Backtrace {
+        let mut rv = Backtrace {
+            repr: BacktraceRepr::Frames(frames),
+            truncated,
+            ..Default::default()
+        };
+        rv.info_mut().os_error = os_error;
+        rv.with_current_thread()
+    }
+
+    fn info_mut(&mut self) -> &mut CaptureInfo {
+        self.info.get_or_insert_with(Default::default)
+    }
+
+    /// Records the current thread unless the backtrace knows its thread.
+    fn with_current_thread(mut self) -> Backtrace {
+        let info = self.info_mut();
+        if info.thread_id.is_none() {
+            let thread = thread::current();
+            info.thread_name = thread.name().map(|x| x.to_string());
+            info.thread_id = Some(thread.id());
+        }
+        self
+    }
+
+    /// Creates a single frame backtrace for an instruction pointer.
+    ///
+    /// Unlike the capture functions this does not unwind the stack.  The
+    /// frame is resolved lazily like captured frames, which makes this
+    /// useful for one-off address lookups, for instance of addresses
+    /// reported by a JIT.
+    pub fn capture_at(ip: *mut c_void, hint: AddrHint) -> Backtrace {
+        Backtrace::from_frames(vec![Frame::new(ip, hint)])
+    }
+
+    /// Creates a backtrace from raw instruction pointers.
+    ///
+    /// Every pointer becomes a frame with the given hint that is resolved
+    /// lazily like captured frames.  The pointers must belong to the current
+    /// process but can come from anywhere, for instance from a stalled
+    /// thread sampled by a watchdog.  They are expected to be ordered with
+    /// the most recent call first.
+    pub fn from_raw_ips(ips: &[*mut c_void], hint: AddrHint) -> Backtrace {
+        Backtrace::from_frames(ips.iter().map(|&ip| Frame::new(ip, hint)).collect())
+    }
+
+    /// Creates a single frame backtrace from a source location.
+    ///
+    /// The frame has no instruction pointer, its only symbol carries the
+    /// file, line and column of the location.  Combined with
+    /// `#[track_caller]` and `std::panic::Location::caller()` this records
+    /// where an error was created without unwinding the stack.
+    pub fn from_location(loc: &'static Location<'static>) -> Backtrace {
+        Backtrace::from_frames(vec![Frame::from_location(loc)])
+    }
+
     /// Checks if backtraces are generally supported.
+    ///
+    /// Without the `backtrace` feature this is only the case if a
+    /// `BacktraceStrategy` is installed.
     pub fn supported() -> bool {
-        cfg!(feature = "backtrace")
+        cfg!(feature = "backtrace") || current_strategy().is_some()
     }
 
     /// Captures the backtrace at the current position.
     ///
+    /// The location of the caller is recorded and available as `caller()`,
+    /// use `capture_with_caller` to pass it through your own wrapper.  It is
+    /// shown in the header of the output but does not add a frame, so the
+    /// frames are the same as the ones of `capture_with_limit`.
+    ///
+    /// Captures do not nest: if a capture is started on a thread that is
+    /// already capturing (for instance from a `capture_filtered` callback)
+    /// the nested capture returns an empty backtrace.
+    ///
     /// If the platform does not support backtrace capturing then `None` is
     /// returned.
     #[inline(never)]
+    #[track_caller]
     pub fn capture() -> Option<Backtrace> {
+        Backtrace::capture_with_caller(Location::caller())
+    }
+
+    /// Captures the backtrace at the current position for a caller.
+    ///
+    /// This works like `capture()` but records the given location as the
+    /// caller.  Wrappers annotated with `#[track_caller]` can pass
+    /// `Location::caller()` so the true call site is reported even if the
+    /// wrapper itself shows up in the frames.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_with_caller(loc: &'static Location<'static>) -> Option<Backtrace> {
+        Backtrace::capture_with_limit(usize::MAX).map(|mut bt| {
+            bt.info_mut().caller = Some(loc);
+            bt
+        })
+    }
+
+    /// Captures the backtrace at the current position with a frame limit.
+    ///
+    /// At most `max_frames` frames (and never more than the limit set with
+    /// `set_max_backtrace_frames`) are recorded, the rest of the stack is
+    /// not unwound.  If frames were dropped this way the backtrace is marked
+    /// as truncated.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_with_limit(max_frames: usize) -> Option<Backtrace> {
         #[cfg(feature = "backtrace")]
         {
-            Some(Backtrace {
-                repr: BacktraceRepr::Frames(capture_backtrace(false)),
-            })
+            let os_error = last_os_error();
+            let (frames, truncated) = capture_backtrace(max_frames);
+            Some(Backtrace::captured(frames, truncated, os_error))
         }
         #[cfg(not(feature = "backtrace"))]
         {
+            capture_with_strategy(0, max_frames)
+        }
+    }
+
+    /// Captures the backtrace at the current position for 1 in `rate` calls.
+    ///
+    /// Calls are counted per thread and the first call of every `rate`
+    /// calls captures, all others return `None` without touching the stack.
+    /// This keeps representative backtraces for hot error paths.  A `rate`
+    /// of `0` never captures.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_sampled(rate: u32) -> Option<Backtrace> {
+        thread_local! {
+            static SAMPLE_COUNTER: Cell<u32> = const { Cell::new(0) };
+        }
+        if rate == 0 {
+            return None;
+        }
+        let count = SAMPLE_COUNTER.with(|counter| {
+            let count = counter.get();
+            counter.set(if count + 1 >= rate { 0 } else { count + 1 });
+            count
+        });
+        if count == 0 {
+            Backtrace::capture_with_limit(usize::MAX)
+        } else {
             None
         }
     }
 
+    /// Captures the frames at the current position into a buffer.
+    ///
+    /// The buffer is cleared first, so reusing it for repeated captures
+    /// avoids allocating a new vector each time.  The frames can be turned
+    /// into a backtrace with `from_frames` when needed.  Frames beyond the
+    /// limit set with `set_max_backtrace_frames` are left out.
+    ///
+    /// Returns `false` if the platform does not support backtrace
+    /// capturing.
+    #[inline(never)]
+    pub fn capture_into(buf: &mut Vec<Frame>) -> bool {
+        buf.clear();
+        #[cfg(feature = "backtrace")]
+        {
+            capture_backtrace_into(buf, usize::MAX);
+            true
+        }
+        #[cfg(not(feature = "backtrace"))]
+        {
+            match capture_with_strategy(0, usize::MAX) {
+                Some(bt) => {
+                    if let BacktraceRepr::Frames(frames) = bt.repr {
+                        buf.extend(frames);
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// Captures a light backtrace at the current position.
+    ///
+    /// This only records three physical frames starting with the caller of
+    /// this function, which makes it considerably cheaper than a full
+    /// capture while still identifying the call site.  Frames of the
+    /// capturing machinery are not counted.  If the calling frame cannot be
+    /// located on the stack the frames are counted from the top of the raw
+    /// stack instead.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_light() -> Option<Backtrace> {
+        #[cfg(feature = "backtrace")]
+        {
+            let os_error = last_os_error();
+            let marker = Backtrace::capture_light as *const () as usize;
+            let (frames, truncated) = capture_backtrace_after(marker, 0, 3);
+            Some(Backtrace::captured(frames, truncated, os_error))
+        }
+        #[cfg(not(feature = "backtrace"))]
+        {
+            capture_with_strategy(0, 3)
+        }
+    }
+
+    /// Captures the backtrace at the current position skipping frames.
+    ///
+    /// The first `n` frames above the caller of this function are discarded
+    /// before anything is resolved, which is useful to hide helper functions
+    /// that construct errors.  Note that this operates on physical frames, so
+    /// functions that were inlined into a frame cannot be skipped on their
+    /// own.  If the calling frame cannot be located on the stack the frames
+    /// are counted from the top of the raw stack instead.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_skipping(n: usize) -> Option<Backtrace> {
+        #[cfg(feature = "backtrace")]
+        {
+            let os_error = last_os_error();
+            let marker = Backtrace::capture_skipping as *const () as usize;
+            let (frames, truncated) = capture_backtrace_after(marker, n, usize::MAX);
+            Some(Backtrace::captured(frames, truncated, os_error))
+        }
+        #[cfg(not(feature = "backtrace"))]
+        {
+            capture_with_strategy(n, usize::MAX)
+        }
+    }
+
+    /// Captures a range of frames of the backtrace at the current position.
+    ///
+    /// This combines `capture_skipping` and `capture_with_limit`: the first
+    /// `skip` frames above the caller of this function are discarded and up
+    /// to `take` of the following frames are recorded, all within a single
+    /// walk of the stack.  If more frames exist the backtrace is marked as
+    /// truncated.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_range(skip: usize, take: usize) -> Option<Backtrace> {
+        #[cfg(feature = "backtrace")]
+        {
+            let os_error = last_os_error();
+            let marker = Backtrace::capture_range as *const () as usize;
+            let (frames, truncated) = capture_backtrace_after(marker, skip, take);
+            Some(Backtrace::captured(frames, truncated, os_error))
+        }
+        #[cfg(not(feature = "backtrace"))]
+        {
+            capture_with_strategy(skip, take)
+        }
+    }
+
+    /// Captures the backtrace at the current position keeping only some
+    /// frames.
+    ///
+    /// The callback is invoked with the raw instruction pointer of every
+    /// frame while the stack is unwound and frames it rejects are never
+    /// recorded.  As nothing is resolved at this point this is a cheap way
+    /// to prune frames by address, for instance those of a loaded plugin.
+    /// The frame limit applies to the frames kept.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_filtered<F: FnMut(*mut c_void) -> bool>(mut keep: F) -> Option<Backtrace> {
+        #[cfg(feature = "backtrace")]
+        {
+            let os_error = last_os_error();
+            let max_frames = max_backtrace_frames();
+            let mut truncated = false;
+            let mut frames = vec![];
+            let mut first = true;
+            guarded_trace(|frame| {
+                let hint = if first {
+                    AddrHint::Precise
+                } else {
+                    AddrHint::Return
+                };
+                first = false;
+                if !keep(frame.ip()) {
+                    return true;
+                }
+                if frames.len() >= max_frames {
+                    truncated = true;
+                    return false;
+                }
+                frames.push(Frame::from_traced(frame, hint));
+                true
+            });
+            Some(Backtrace::captured(frames, truncated, os_error))
+        }
+        #[cfg(not(feature = "backtrace"))]
+        {
+            capture_with_strategy(0, usize::MAX).map(|mut bt| {
+                if let BacktraceRepr::Frames(ref mut frames) = bt.repr {
+                    frames.retain(|frame| keep(frame.ip()));
+                }
+                bt
+            })
+        }
+    }
+
     /// Captures the backtrace specific for the current purpose.
     ///
+    /// The purpose specific environment variable (`RUST_PANIC_BACKTRACE`,
+    /// `RUST_ERROR_BACKTRACE` or the custom variable) takes precedence over
+    /// `RUST_BACKTRACE`.  Like the standard library, errors also honor
+    /// `RUST_LIB_BACKTRACE` which ranks between the two.  The values `1`,
+    /// `true`, `yes`, `on`, `full` and `short` enable capturing, `0`,
+    /// `false`, `no`, `off` and the empty string disable it.  Values are
+    /// matched case insensitively.  If `full` is requested the backtrace
+    /// remembers this and will not be trimmed.
+    ///
     /// If the platform does not support backtrace capturing then `None` is
     /// returned.
     pub fn conditional_capture(purpose: CapturePurpose) -> Option<Backtrace> {
-        let var = match purpose {
-            CapturePurpose::Panic => "RUST_PANIC_BACKTRACE",
-            CapturePurpose::Error => "RUST_ERROR_BACKTRACE",
-        };
+        match purpose_env_setting(purpose) {
+            EnvSetting::Off => None,
+            EnvSetting::Short => Backtrace::capture_for(purpose),
+            EnvSetting::Full => Backtrace::capture_for(purpose).map(|mut bt| {
+                bt.full = true;
+                bt
+            }),
+        }
+    }
 
-        match env::var(var).as_ref().map(|x| x.as_str()).ok() {
-            Some("1") | Some("full") => {
-                return Backtrace::capture();
-            }
-            Some("0") => {
-                return None;
+    /// Captures a full or light backtrace depending on `RUST_BACKTRACE`.
+    ///
+    /// If backtraces are enabled through `RUST_BACKTRACE` (with the same
+    /// values `conditional_capture` accepts) the full backtrace is captured,
+    /// otherwise a light one as with `capture_light`.  This always gives
+    /// some cheap provenance while honoring requests for full backtraces.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_auto() -> Option<Backtrace> {
+        match backtrace_env_setting("RUST_BACKTRACE").unwrap_or(EnvSetting::Off) {
+            // not forwarded to `capture_light` which would count this frame
+            #[cfg(feature = "backtrace")]
+            EnvSetting::Off => {
+                let os_error = last_os_error();
+                let marker = Backtrace::capture_auto as *const () as usize;
+                let (frames, truncated) = capture_backtrace_after(marker, 0, 3);
+                Some(Backtrace::captured(frames, truncated, os_error))
             }
-            _ => {}
+            #[cfg(not(feature = "backtrace"))]
+            EnvSetting::Off => capture_with_strategy(0, 3),
+            EnvSetting::Short => Backtrace::capture_with_limit(usize::MAX),
+            EnvSetting::Full => Backtrace::capture_with_limit(usize::MAX).map(|mut bt| {
+                bt.full = true;
+                bt
+            }),
         }
+    }
 
-        match env::var("RUST_BACKTRACE").as_ref().map(|x| x.as_str()).ok() {
-            Some("1") | Some("full") => Backtrace::capture(),
-            _ => None,
-        }
+    /// Checks if `conditional_capture` would capture a backtrace.
+    ///
+    /// This consults the same environment variables and also requires
+    /// backtraces to be supported.  It allows skipping expensive work that
+    /// is only needed for errors carrying a backtrace.
+    pub fn is_enabled(purpose: CapturePurpose) -> bool {
+        Backtrace::supported() && purpose_env_setting(purpose) != EnvSetting::Off
+    }
+
+    /// Captures the backtrace for a purpose regardless of the environment.
+    ///
+    /// This works like `capture()` but records the purpose which can later
+    /// be retrieved with `purpose()`, for instance to format backtraces of
+    /// panics differently from those of errors.
+    ///
+    /// If the platform does not support backtrace capturing then `None` is
+    /// returned.
+    #[inline(never)]
+    pub fn capture_for(purpose: CapturePurpose) -> Option<Backtrace> {
+        Backtrace::capture_with_limit(usize::MAX).map(|mut bt| {
+            bt.purpose = Some(purpose);
+            bt
+        })
+    }
+
+    /// Returns the purpose the backtrace was captured for.
+    ///
+    /// This is only known for backtraces created by `capture_for` or
+    /// `conditional_capture`.
+    pub fn purpose(&self) -> Option<CapturePurpose> {
+        self.purpose
+    }
+
+    /// Returns the location `capture()` was called from.
+    ///
+    /// This is only known for backtraces created by `capture` or
+    /// `capture_with_caller`, which also show it in their header.
+    pub fn caller(&self) -> Option<&'static Location<'static>> {
+        self.info.as_ref()?.caller
+    }
+
+    /// Returns the name of the thread the backtrace was captured on.
+    ///
+    /// This is only known for captured backtraces and if the thread has a
+    /// name.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.info.as_ref()?.thread_name.as_deref()
+    }
+
+    /// Returns the id of the thread the backtrace was captured on.
+    ///
+    /// This is only known for captured backtraces.
+    pub fn thread_id(&self) -> Option<ThreadId> {
+        self.info.as_ref()?.thread_id
+    }
+
+    /// Returns the last OS error code at the time of capture.
+    ///
+    /// This is the value of `errno` (or `GetLastError` on Windows) right
+    /// before the stack was unwound and is only known for captured
+    /// backtraces.  It is recorded on a best effort basis: the code is not
+    /// reset on success, so it might be stale and unrelated to the error the
+    /// backtrace belongs to.
+    pub fn os_error(&self) -> Option<i32> {
+        self.info.as_ref()?.os_error
+    }
+
+    /// Returns `true` if a full backtrace was requested through the environment.
+    ///
+    /// This is the case if `conditional_capture` found a value of `full` in
+    /// the relevant environment variable.
+    pub fn was_full_requested(&self) -> bool {
+        self.full
     }
 
     /// Checks if the stacktrace is empty.
     pub fn is_empty(&self) -> bool {
         match self.repr {
             BacktraceRepr::Empty => true,
-            #[cfg(feature = "backtrace")]
             BacktraceRepr::Frames(ref frames) => frames.is_empty(),
         }
     }
 
+    /// Returns the number of frames in the stacktrace.
+    pub fn len(&self) -> usize {
+        match self.repr {
+            BacktraceRepr::Empty => 0,
+            BacktraceRepr::Frames(ref frames) => frames.len(),
+        }
+    }
+
+    /// Returns `true` if frames were dropped during capture.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Resolves the symbols of all frames right away.
+    ///
+    /// Symbols are normally resolved lazily on first access.  Resolving them
+    /// up front makes the backtrace self contained which is useful before
+    /// it's sent elsewhere or the address space of the process changes.
+    pub fn resolve(&self) {
+        for frame in self.iter_frames() {
+            frame.symbols();
+        }
+    }
+
+    /// Resolves the symbols of frames until the time budget is exhausted.
+    ///
+    /// Returns `true` if all frames are resolved.  Frames that could not be
+    /// resolved within the budget stay lazy and are resolved on access.  The
+    /// budget is checked between frames so a single slow frame can exceed it.
+    pub fn resolve_within(&self, budget: Duration) -> bool {
+        let start = Instant::now();
+        for frame in self.iter_frames() {
+            if frame.resolved.get().is_some() {
+                continue;
+            }
+            if start.elapsed() >= budget {
+                return false;
+            }
+            frame.symbols();
+        }
+        true
+    }
+
     /// Automatically trim the stacktrace.
     ///
     /// This removes uninteresting frames from the top and bottom of the
@@ -373,145 +1697,1099 @@ impl Backtrace {
     /// all internal frames from the backtrace system itself as well as
     /// frames below the user's main function.
     pub fn trimmed(self) -> Backtrace {
-        #[cfg(feature = "backtrace")]
-        {
-            let frameiter = match self.repr {
-                BacktraceRepr::Empty => return self,
-                BacktraceRepr::Frames(frames) => frames.into_iter(),
+        self.trimmed_with(&TrimOptions::default())
+    }
+
+    /// Trims the stacktrace with custom options.
+    ///
+    /// This works like `trimmed()` but the given options can declare
+    /// additional internal and boundary frames.  If no internal frames are
+    /// found at all, only the bottom of the stacktrace is trimmed.  Frames
+    /// without symbol information are dropped unless the options keep them.
+    ///
+    /// Backtraces captured while a full backtrace was requested (see
+    /// `was_full_requested`) are returned unchanged.
+    pub fn trimmed_with(self, options: &TrimOptions) -> Backtrace {
+        if self.full {
+            return self;
+        }
+
+        let frameiter = match self.repr {
+            BacktraceRepr::Empty => return self,
+            BacktraceRepr::Frames(frames) => frames.into_iter(),
+        };
+
+        enum State {
+            BeforeBacktraceInternal,
+            FoundBacktraceInternal,
+            InStack,
+        }
+
+        // symbols are tagged with the index of their frame so that
+        // recursive calls with identical addresses stay separate frames.
+        // Kept unresolved frames contribute a single `None` entry.
+        let symbols: Vec<_> = frameiter
+            .enumerate()
+            .flat_map(|(idx, x)| {
+                let ip = x.ip();
+                let addr_hint = x.addr_hint();
+                let symbols = x.take_symbols();
+                let unresolved = symbols
+                    .iter()
+                    .all(|s| s.name().is_none() && s.filename().is_none());
+                let entries: Vec<_> = if unresolved && !options.keep_unresolved {
+                    vec![]
+                } else if symbols.is_empty() {
+                    vec![None]
+                } else {
+                    symbols.into_iter().map(Some).collect()
+                };
+                entries.into_iter().map(move |s| (idx, ip, addr_hint, s))
+            })
+            .collect();
+
+        let is_internal = |symbol: &Option<Symbol>| {
+            symbol
+                .as_ref()
+                .is_some_and(|s| s.is_backtrace_internal(options))
+        };
+
+        let mut state = if symbols.iter().any(|(_, _, _, symbol)| is_internal(symbol)) {
+            State::BeforeBacktraceInternal
+        } else {
+            State::InStack
+        };
+
+        let mut pending_frame: Option<(usize, *mut c_void, AddrHint, Vec<Symbol>)> = None;
+        let mut rv = vec![];
+
+        for (idx, ip, addr_hint, symbol) in symbols {
+            match state {
+                State::BeforeBacktraceInternal => {
+                    if is_internal(&symbol) {
+                        state = State::FoundBacktraceInternal;
+                    }
+                    continue;
+                }
+                State::FoundBacktraceInternal => {
+                    if is_internal(&symbol) {
+                        continue;
+                    }
+                    state = State::InStack;
+                }
+                State::InStack => {
+                    if symbol
+                        .as_ref()
+                        .is_some_and(|s| s.is_end_of_user_code(options))
+                    {
+                        break;
+                    }
+                }
+            }
+
+            match pending_frame {
+                Some((cur_idx, _, _, ref mut symbols)) if cur_idx == idx => {
+                    symbols.extend(symbol);
+                }
+                _ => {
+                    if let Some((_, cur_ip, cur_addr_hint, symbols)) = pending_frame.take() {
+                        rv.push(Frame::new_resolved(cur_ip, cur_addr_hint, symbols));
+                    }
+                    pending_frame = Some((idx, ip, addr_hint, symbol.into_iter().collect()));
+                }
+            }
+        }
+
+        if let Some((_, ip, addr_hint, symbols)) = pending_frame {
+            rv.push(Frame::new_resolved(ip, addr_hint, symbols));
+        }
+
+        Backtrace {
+            repr: BacktraceRepr::Frames(rv),
+            ..self
+        }
+    }
+
+    /// Strips a path prefix from the filenames of all symbols.
+    ///
+    /// This resolves all frames and makes filenames within `base` relative
+    /// to it, for instance to avoid leaking build paths in crash reports.
+    /// Filenames outside of `base` are left intact.
+    pub fn strip_path_prefix(self, base: &Path) -> Backtrace {
+        match self.repr {
+            BacktraceRepr::Empty => self,
+            BacktraceRepr::Frames(mut frames) => {
+                for frame in &mut frames {
+                    frame.symbols();
+                    for sym in frame.resolved.get_mut().into_iter().flatten() {
+                        if let Some(path) = sym.relative_path(base) {
+                            sym.filename = Some(intern_path(&path));
+                        }
+                    }
+                }
+                Backtrace {
+                    repr: BacktraceRepr::Frames(frames),
+                    ..self
+                }
+            }
+        }
+    }
+
+    /// Expands every frame into one frame per symbol.
+    ///
+    /// Depending on the debug information the same stack can come back as
+    /// frames with inlined symbols or as one frame per function.  Splitting
+    /// normalizes to the latter so fingerprints and diffs are stable across
+    /// optimization levels.  The new frames share the instruction pointer
+    /// and address hint of the frame they were split from.  To merge them
+    /// back, consecutive frames with the same instruction pointer can be
+    /// grouped again, although this also merges directly recursive calls.
+    pub fn split_inlined(self) -> Backtrace {
+        match self.repr {
+            BacktraceRepr::Empty => self,
+            BacktraceRepr::Frames(frames) => {
+                let mut rv = Vec::with_capacity(frames.len());
+                for frame in frames {
+                    if frame.symbols().len() <= 1 {
+                        rv.push(frame);
+                        continue;
+                    }
+                    let ip = frame.ip();
+                    let hint = frame.addr_hint();
+                    for symbol in frame.take_symbols() {
+                        rv.push(Frame::new_resolved(ip, hint, vec![symbol]));
+                    }
+                }
+                Backtrace {
+                    repr: BacktraceRepr::Frames(rv),
+                    ..self
+                }
+            }
+        }
+    }
+
+    /// Collapses runs of frames with the same symbols into single frames.
+    ///
+    /// Deep recursion produces many identical consecutive frames, these are
+    /// merged into the first frame of the run which remembers how many
+    /// frames it stands for (see `Frame::repeat_count`) and displays it as
+    /// `(×N)`.  Frames are compared by the names of their symbols, frames
+    /// without any symbol names are never collapsed.
+    pub fn collapse_recursion(self) -> Backtrace {
+        match self.repr {
+            BacktraceRepr::Empty => self,
+            BacktraceRepr::Frames(frames) => {
+                let mut rv: Vec<Frame> = Vec::with_capacity(frames.len());
+                for frame in frames {
+                    if let Some(last) = rv.last_mut() {
+                        if same_symbol_names(last, &frame) {
+                            last.repeat += frame.repeat;
+                            continue;
+                        }
+                    }
+                    rv.push(frame);
+                }
+                Backtrace {
+                    repr: BacktraceRepr::Frames(rv),
+                    ..self
+                }
+            }
+        }
+    }
+
+    /// Retains only the frames for which the predicate returns `true`.
+    ///
+    /// Symbols that were already resolved are kept, so this can be freely
+    /// combined with `trimmed()`.
+    pub fn filter_frames<F: Fn(&Frame) -> bool>(self, keep: F) -> Backtrace {
+        match self.repr {
+            BacktraceRepr::Empty => self,
+            BacktraceRepr::Frames(frames) => Backtrace {
+                repr: BacktraceRepr::Frames(frames.into_iter().filter(|x| keep(x)).collect()),
+                ..self
+            },
+        }
+    }
+
+    /// Compares the frames with those of another backtrace.
+    ///
+    /// Frames are compared by the names of their symbols, so this resolves
+    /// both backtraces.  When two captures of the same logical point share
+    /// the frames of their callers, the diff tells where the code paths
+    /// diverge.
+    pub fn diff(&self, other: &Backtrace) -> BacktraceDiff {
+        let (a, b) = (self.frames(), other.frames());
+        let same = |x: &Frame, y: &Frame| {
+            x.symbols()
+                .iter()
+                .map(|s| s.name.as_deref())
+                .eq(y.symbols().iter().map(|s| s.name.as_deref()))
+        };
+        let common_prefix = a.iter().zip(b).take_while(|&(x, y)| same(x, y)).count();
+        let common_suffix = a[common_prefix..]
+            .iter()
+            .rev()
+            .zip(b[common_prefix..].iter().rev())
+            .take_while(|&(x, y)| same(x, y))
+            .count();
+        BacktraceDiff {
+            common_prefix,
+            common_suffix,
+            identical: common_prefix == a.len() && common_prefix == b.len(),
+        }
+    }
+
+    /// Keeps at most the `n` most recent frames.
+    ///
+    /// Unlike the limit of `capture_with_limit` this applies after the fact,
+    /// for instance to a trimmed backtrace before it is stored.  If frames
+    /// are dropped the backtrace is marked as truncated.
+    pub fn truncate(&mut self, n: usize) {
+        if let BacktraceRepr::Frames(ref mut frames) = self.repr {
+            if frames.len() > n {
+                frames.truncate(n);
+                self.truncated = true;
+            }
+        }
+    }
+
+    /// Iterates over the frames.
+    pub fn iter_frames<'a>(&'a self) -> FrameIter<'a> {
+        FrameIter {
+            bt: &self.repr,
+            idx: 0,
+            end: self.frames().len(),
+        }
+    }
+
+    /// Iterates over the frames starting with the oldest call.
+    pub fn iter_frames_rev<'a>(&'a self) -> iter::Rev<FrameIter<'a>> {
+        self.iter_frames().rev()
+    }
+
+    /// Returns the frames as a slice.
+    ///
+    /// This is empty for backtraces that do not carry frames.
+    pub fn frames(&self) -> &[Frame] {
+        match self.repr {
+            BacktraceRepr::Empty => &[],
+            BacktraceRepr::Frames(ref frames) => &frames[..],
+        }
+    }
+
+    /// Consumes the backtrace and returns its frames.
+    ///
+    /// Together with `from_frames` this allows building a backtrace from
+    /// modified frames.  Symbols that were already resolved are kept.
+    pub fn into_frames(self) -> Vec<Frame> {
+        match self.repr {
+            BacktraceRepr::Empty => Vec::new(),
+            BacktraceRepr::Frames(frames) => frames,
+        }
+    }
+
+    /// Returns a fingerprint of the backtrace.
+    ///
+    /// The fingerprint is derived from the same data as the equality check
+    /// and does not depend on addresses, so it can be used to group
+    /// identical backtraces across runs.  Unlike `Hash` the value is stable
+    /// between processes and compiler versions.
+    pub fn fingerprint(&self) -> u64 {
+        // 64 bit FNV-1a
+        let mut rv: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for &b in bytes {
+                rv ^= u64::from(b);
+                rv = rv.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for (_, _, sym) in self.iter_symbols() {
+            let (name, filename, lineno) = sym.identity();
+            feed(name.unwrap_or(b""));
+            feed(b"\0");
+            feed(
+                filename
+                    .map_or(Cow::Borrowed(""), |x| x.to_string_lossy())
+                    .as_bytes(),
+            );
+            feed(b"\0");
+            feed(&lineno.unwrap_or(0).to_le_bytes());
+        }
+        rv
+    }
+
+    /// Returns a value that displays the backtrace on a single line.
+    ///
+    /// Only the symbol names are shown, most recent call first, separated by
+    /// `<-`.  This is useful for structured logging.
+    pub fn compact_display(&self) -> impl fmt::Display + '_ {
+        CompactBacktrace(self)
+    }
+
+    /// Returns a value that displays only the top `n` frames.
+    ///
+    /// This works on a trimmed copy of the backtrace so only user frames
+    /// are counted.  If frames are left out the output ends with a line
+    /// stating how many.  This is useful for user facing error messages.
+    pub fn short_display(&self, n: usize) -> impl fmt::Display {
+        ShortBacktrace {
+            backtrace: self.clone().trimmed(),
+            n,
+        }
+    }
+
+    /// Returns a value that displays the backtrace with ANSI colors.
+    ///
+    /// Symbol names are highlighted and file locations dimmed, otherwise
+    /// the output matches `Display` including the alternate form.  Colors
+    /// are not emitted if `NO_COLOR` is set or `CLICOLOR` is `0`.
+    #[cfg(feature = "color")]
+    pub fn display_colored(&self) -> impl fmt::Display + '_ {
+        ColoredBacktrace(self)
+    }
+
+    /// Iterates over the symbols of all frames.
+    ///
+    /// Each symbol is yielded together with the instruction pointer and
+    /// address hint of the frame it belongs to.
+    pub fn iter_symbols(&self) -> SymbolIter<'_> {
+        SymbolIter {
+            frames: self.iter_frames(),
+            current: None,
+        }
+    }
+
+    /// Writes the backtrace to a writer.
+    ///
+    /// This produces the same output as the `Display` impl (or its alternate
+    /// form if `alternate` is set) but writes frame by frame instead of
+    /// formatting the whole backtrace into a string first.
+    pub fn write_to<W: io::Write>(&self, w: &mut W, alternate: bool) -> io::Result<()> {
+        let name_width = self.name_column_width();
+        write!(w, "{}", BacktraceHeader(self))?;
+        for frame in self.iter_frames() {
+            if alternate {
+                write!(w, "\n{:#1$}", frame, name_width)?;
+            } else {
+                write!(w, "\n{}", frame)?;
+            }
+        }
+        if self.truncated {
+            write!(w, "\n  ... (truncated)")?;
+        }
+        Ok(())
+    }
+
+    /// Converts the backtrace into a machine readable string.
+    ///
+    /// Every frame is written on its own line in the form
+    /// `ip|hint|name|addr|file|line|column` where the last five fields repeat for
+    /// every symbol of the frame.  Missing values are left empty and a
+    /// truncated backtrace ends with a `...` line.  The output can be parsed
+    /// back with `from_string_repr`.
+    pub fn to_string_repr(&self) -> String {
+        let mut rv = String::new();
+        for frame in self.iter_frames() {
+            rv.push_str(&format!("{:#x}|", frame.ip));
+            rv.push_str(match frame.hint {
+                AddrHint::Precise => "precise",
+                AddrHint::Return => "return",
+                AddrHint::Unknown => "unknown",
+            });
+            for sym in frame.symbols() {
+                rv.push('|');
+                if let Some(ref name) = sym.name {
+                    escape_repr_field(&mut rv, name);
+                }
+                rv.push('|');
+                if let Some(addr) = sym.addr {
+                    rv.push_str(&format!("{:#x}", addr));
+                }
+                rv.push('|');
+                if let Some(ref filename) = sym.filename {
+                    escape_repr_field(&mut rv, filename.to_string_lossy().as_bytes());
+                }
+                rv.push('|');
+                if let Some(lineno) = sym.lineno {
+                    rv.push_str(&lineno.to_string());
+                }
+                rv.push('|');
+                if let Some(column) = sym.column {
+                    rv.push_str(&column.to_string());
+                }
+            }
+            rv.push('\n');
+        }
+        if self.truncated {
+            rv.push_str("...\n");
+        }
+        rv
+    }
+
+    /// Parses a backtrace from the format produced by `to_string_repr`.
+    ///
+    /// The symbols are taken from the string, no resolution against the
+    /// current process is performed.
+    pub fn from_string_repr(s: &str) -> Result<Backtrace, ParseError> {
+        let mut frames = vec![];
+        let mut truncated = false;
+
+        for (idx, line) in s.lines().enumerate() {
+            let err = |msg| ParseError { line: idx + 1, msg };
+            if line.is_empty() {
+                continue;
+            }
+            if truncated {
+                return Err(err("unexpected data after truncation marker"));
+            }
+            if line == "..." {
+                truncated = true;
+                continue;
+            }
+
+            let fields = split_repr_line(line).ok_or_else(|| err("invalid escape sequence"))?;
+            if fields.len() < 2 || (fields.len() - 2) % 5 != 0 {
+                return Err(err("unexpected number of fields"));
+            }
+
+            let ip = str::from_utf8(&fields[0])
+                .ok()
+                .and_then(parse_repr_addr)
+                .ok_or_else(|| err("invalid instruction pointer"))?;
+            let hint = match &fields[1][..] {
+                b"precise" => AddrHint::Precise,
+                b"return" => AddrHint::Return,
+                b"unknown" => AddrHint::Unknown,
+                _ => return Err(err("invalid address hint")),
             };
 
-            enum State {
-                BeforeBacktraceInternal,
-                FoundBacktraceInternal,
-                InStack,
+            let mut symbols = vec![];
+            for chunk in fields[2..].chunks(5) {
+                let opt = |field: &Vec<u8>| {
+                    if field.is_empty() {
+                        None
+                    } else {
+                        Some(field.clone())
+                    }
+                };
+                let addr = match opt(&chunk[1]) {
+                    Some(addr) => Some(
+                        str::from_utf8(&addr)
+                            .ok()
+                            .and_then(parse_repr_addr)
+                            .ok_or_else(|| err("invalid symbol address"))?,
+                    ),
+                    None => None,
+                };
+                let num = |field: &Vec<u8>, msg| match opt(field) {
+                    Some(num) => str::from_utf8(&num)
+                        .ok()
+                        .and_then(|x| x.parse().ok())
+                        .map(Some)
+                        .ok_or_else(|| err(msg)),
+                    None => Ok(None),
+                };
+                symbols.push(Symbol {
+                    name: opt(&chunk[0]),
+                    addr,
+                    filename: opt(&chunk[2])
+                        .map(|x| intern_path(Path::new(&*String::from_utf8_lossy(&x)))),
+                    lineno: num(&chunk[3], "invalid line number")?,
+                    column: num(&chunk[4], "invalid column number")?,
+                });
             }
 
-            let mut state = State::BeforeBacktraceInternal;
-            let symbols = frameiter.flat_map(|x| {
-                let ip = x.ip();
-                let addr_hint = x.addr_hint();
-                x.take_symbols()
-                    .into_iter()
-                    .map(move |s| (ip, addr_hint, s))
-            });
+            frames.push(Frame::new_resolved(ip as *mut c_void, hint, symbols));
+        }
+
+        let mut rv = Backtrace::from_frames(frames);
+        rv.truncated = truncated;
+        Ok(rv)
+    }
+}
+
+/// The result of comparing two backtraces with `Backtrace::diff`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BacktraceDiff {
+    common_prefix: usize,
+    common_suffix: usize,
+    identical: bool,
+}
+
+impl BacktraceDiff {
+    /// The number of matching frames at the top (the most recent calls).
+    pub fn common_prefix(&self) -> usize {
+        self.common_prefix
+    }
+
+    /// The number of matching frames at the bottom (the oldest calls).
+    ///
+    /// This never overlaps with the common prefix.
+    pub fn common_suffix(&self) -> usize {
+        self.common_suffix
+    }
+
+    /// The index of the first frame from the top that differs.
+    ///
+    /// This is `None` if both backtraces have the same frames.
+    pub fn first_difference(&self) -> Option<usize> {
+        if self.identical {
+            None
+        } else {
+            Some(self.common_prefix)
+        }
+    }
+
+    /// Returns `true` if both backtraces have the same frames.
+    pub fn is_identical(&self) -> bool {
+        self.identical
+    }
+}
+
+/// An error that is returned when parsing a backtrace from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    msg: &'static str,
+}
+
+impl ParseError {
+    /// The line (starting at 1) on which the error occurred.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (line {})", self.msg, self.line)
+    }
+}
+
+impl error::Error for ParseError {}
+
+fn escape_repr_field(out: &mut String, bytes: &[u8]) {
+    let is_utf8 = str::from_utf8(bytes).is_ok();
+    let mut utf8_start = None;
+    for (idx, &b) in bytes.iter().enumerate() {
+        if is_utf8 && b >= 0x80 {
+            utf8_start.get_or_insert(idx);
+            continue;
+        }
+        if let Some(start) = utf8_start.take() {
+            out.push_str(str::from_utf8(&bytes[start..idx]).unwrap());
+        }
+        match b {
+            b'|' => out.push_str("\\|"),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    if let Some(start) = utf8_start {
+        out.push_str(str::from_utf8(&bytes[start..]).unwrap());
+    }
+}
+
+fn split_repr_line(line: &str) -> Option<Vec<Vec<u8>>> {
+    let mut rv = vec![];
+    let mut field = vec![];
+    let mut iter = line.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'|' => rv.push(mem::take(&mut field)),
+            b'\\' => match iter.next()? {
+                b'x' => {
+                    let hex = [iter.next()?, iter.next()?];
+                    let hex = str::from_utf8(&hex).ok()?;
+                    field.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                b @ b'|' | b @ b'\\' => field.push(b),
+                _ => return None,
+            },
+            b => field.push(b),
+        }
+    }
+    rv.push(field);
+    Some(rv)
+}
+
+fn parse_repr_addr(s: &str) -> Option<usize> {
+    if !s.starts_with("0x") {
+        return None;
+    }
+    usize::from_str_radix(&s[2..], 16).ok()
+}
+
+/// Checks if two frames have the same, named, symbols.
+fn same_symbol_names(a: &Frame, b: &Frame) -> bool {
+    let (a, b) = (a.symbols(), b.symbols());
+    a.iter().any(|x| x.name.is_some())
+        && a.len() == b.len()
+        && a.iter().zip(b).all(|(x, y)| x.name == y.name)
+}
+
+/// An iterator over all frames in a backtrace.
+pub struct FrameIter<'a> {
+    bt: &'a BacktraceRepr,
+    idx: usize,
+    end: usize,
+}
+
+impl<'a> FrameIter<'a> {
+    fn get(&self, idx: usize) -> Option<&'a Frame> {
+        match *self.bt {
+            BacktraceRepr::Empty => None,
+            BacktraceRepr::Frames(ref frames) => frames.get(idx),
+        }
+    }
+}
+
+impl<'a> Iterator for FrameIter<'a> {
+    type Item = &'a Frame;
+
+    fn next(&mut self) -> Option<&'a Frame> {
+        if self.idx >= self.end {
+            return None;
+        }
+        let rv = self.get(self.idx);
+        self.idx += 1;
+        rv
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end.saturating_sub(self.idx);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> DoubleEndedIterator for FrameIter<'a> {
+    fn next_back(&mut self) -> Option<&'a Frame> {
+        if self.idx >= self.end {
+            return None;
+        }
+        self.end -= 1;
+        self.get(self.end)
+    }
+}
+
+impl<'a> ExactSizeIterator for FrameIter<'a> {}
+
+/// An iterator over all symbols in a backtrace.
+pub struct SymbolIter<'a> {
+    frames: FrameIter<'a>,
+    current: Option<(&'a Frame, slice::Iter<'a, Symbol>)>,
+}
+
+impl<'a> Iterator for SymbolIter<'a> {
+    type Item = (*mut c_void, AddrHint, &'a Symbol);
+
+    fn next(&mut self) -> Option<(*mut c_void, AddrHint, &'a Symbol)> {
+        loop {
+            if let Some((frame, ref mut symbols)) = self.current {
+                if let Some(symbol) = symbols.next() {
+                    return Some((frame.ip(), frame.addr_hint(), symbol));
+                }
+            }
+            let frame = self.frames.next()?;
+            self.current = Some((frame, frame.symbols().iter()));
+        }
+    }
+}
+
+/// Backtraces compare equal if they resolve to the same symbols.
+///
+/// Only symbol names, filenames and line numbers are considered.  Addresses
+/// are ignored as they vary between runs.
+impl PartialEq for Backtrace {
+    fn eq(&self, other: &Backtrace) -> bool {
+        self.iter_symbols()
+            .map(|(_, _, sym)| sym.identity())
+            .eq(other.iter_symbols().map(|(_, _, sym)| sym.identity()))
+    }
+}
+
+impl Eq for Backtrace {}
+
+impl Hash for Backtrace {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (_, _, sym) in self.iter_symbols() {
+            sym.identity().hash(state);
+        }
+    }
+}
+
+impl Default for Backtrace {
+    fn default() -> Backtrace {
+        Backtrace {
+            repr: BacktraceRepr::Empty,
+            truncated: false,
+            full: false,
+            purpose: None,
+            info: None,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Backtrace {
+    type Item = &'a Frame;
+    type IntoIter = FrameIter<'a>;
+
+    fn into_iter(self) -> FrameIter<'a> {
+        self.iter_frames()
+    }
+}
+
+/// The value of a backtrace environment variable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EnvSetting {
+    Off,
+    Short,
+    Full,
+}
+
+/// Resolves the backtrace setting for a capture purpose.
+///
+/// The purpose specific variable takes precedence over `RUST_BACKTRACE`,
+/// for errors `RUST_LIB_BACKTRACE` is checked in between.
+fn purpose_env_setting(purpose: CapturePurpose) -> EnvSetting {
+    let (var, lib_var) = match purpose {
+        CapturePurpose::Panic => ("RUST_PANIC_BACKTRACE", None),
+        CapturePurpose::Error => ("RUST_ERROR_BACKTRACE", Some("RUST_LIB_BACKTRACE")),
+        CapturePurpose::Env(var) => (var, None),
+    };
+    backtrace_env_setting(var)
+        .or_else(|| lib_var.and_then(backtrace_env_setting))
+        .or_else(|| backtrace_env_setting("RUST_BACKTRACE"))
+        .unwrap_or(EnvSetting::Off)
+}
+
+/// Reads a backtrace environment variable.
+///
+/// Returns `None` if the variable is not set or has an unknown value.
+fn backtrace_env_setting(var: &str) -> Option<EnvSetting> {
+    let value = env::var(var).ok()?;
+    match &value.to_ascii_lowercase()[..] {
+        "full" => Some(EnvSetting::Full),
+        "1" | "true" | "yes" | "on" | "short" => Some(EnvSetting::Short),
+        "0" | "false" | "no" | "off" | "" => Some(EnvSetting::Off),
+        _ => None,
+    }
+}
+
+/// The default for `set_max_backtrace_frames`.
+const DEFAULT_MAX_BACKTRACE_FRAMES: usize = 128;
+
+static MAX_BACKTRACE_FRAMES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BACKTRACE_FRAMES);
+
+/// Sets the maximum number of frames any capture records.
+///
+/// This bounds the cost of capturing backtraces of runaway recursion, for
+/// instance while handling a stack overflow.  The limit defaults to 128
+/// frames and is meant to be configured once at startup.
+pub fn set_max_backtrace_frames(n: usize) {
+    MAX_BACKTRACE_FRAMES.store(n, Ordering::Relaxed);
+}
+
+/// Reads the last OS error code of the current thread.
+fn last_os_error() -> Option<i32> {
+    io::Error::last_os_error()
+        .raw_os_error()
+        .filter(|&code| code != 0)
+}
+
+fn max_backtrace_frames() -> usize {
+    MAX_BACKTRACE_FRAMES.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "backtrace")]
+thread_local! {
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as capturing until dropped.
+#[cfg(feature = "backtrace")]
+struct CaptureGuard;
+
+#[cfg(feature = "backtrace")]
+impl CaptureGuard {
+    /// Returns `None` if the thread is already capturing.
+    fn enter() -> Option<CaptureGuard> {
+        if CAPTURING.with(|x| x.replace(true)) {
+            None
+        } else {
+            Some(CaptureGuard)
+        }
+    }
+}
+
+#[cfg(feature = "backtrace")]
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        CAPTURING.with(|x| x.set(false));
+    }
+}
+
+/// Walks the stack unless a capture is already in progress on this thread.
+///
+/// Nested captures (for instance from a callback invoked during the walk)
+/// see no frames instead of entering the unwinder again.  Returns `false`
+/// if the stack was not walked.
+#[cfg(feature = "backtrace")]
+fn guarded_trace<F: FnMut(&backtrace_support::Frame) -> bool>(cb: F) -> bool {
+    match CaptureGuard::enter() {
+        Some(_guard) => {
+            backtrace_support::trace(cb);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(feature = "backtrace")]
+fn capture_backtrace(max_frames: usize) -> (Vec<Frame>, bool) {
+    let mut rv = vec![];
+    let truncated = capture_backtrace_into(&mut rv, max_frames);
+    (rv, truncated)
+}
+
+/// Appends the frames of the current stack to `rv`.
+///
+/// Returns `true` if frames were left out due to the limits.
+#[cfg(feature = "backtrace")]
+fn capture_backtrace_into(rv: &mut Vec<Frame>, max_frames: usize) -> bool {
+    let max_frames = max_frames.min(max_backtrace_frames());
+    // static builds (for instance on musl) might lack a working unwinder
+    #[cfg(feature = "fp-unwind")]
+    {
+        trace_with_fallback(rv, max_frames, unwinder_trace, fp_trace)
+    }
+    #[cfg(not(feature = "fp-unwind"))]
+    {
+        unwinder_trace(rv, max_frames).1
+    }
+}
 
-            let mut pending_frame = None;
-            let mut rv = vec![];
+/// Appends the frames found by the unwinder to `rv`.
+///
+/// Returns whether the stack was walked at all and whether frames were left
+/// out due to the limit.
+#[cfg(feature = "backtrace")]
+fn unwinder_trace(rv: &mut Vec<Frame>, max_frames: usize) -> (bool, bool) {
+    let mut truncated = false;
+    let mut count = 0;
+    let walked = guarded_trace(|frame| {
+        if count >= max_frames {
+            truncated = true;
+            return false;
+        }
+        let hint = if count == 0 {
+            AddrHint::Precise
+        } else {
+            AddrHint::Return
+        };
+        rv.push(Frame::from_traced(frame, hint));
+        count += 1;
+        true
+    });
+    (walked, truncated)
+}
 
-            for (ip, addr_hint, symbol) in symbols {
-                match state {
-                    State::BeforeBacktraceInternal => {
-                        if symbol.is_backtrace_internal() {
-                            state = State::FoundBacktraceInternal;
-                        }
-                        continue;
-                    }
-                    State::FoundBacktraceInternal => {
-                        if symbol.is_backtrace_internal() {
-                            continue;
-                        }
-                        state = State::InStack;
-                    }
-                    State::InStack => {
-                        if symbol.is_end_of_user_code() {
-                            break;
-                        }
-                    }
-                }
+/// Runs the primary stack walker and uses the fallback if it found nothing.
+///
+/// The fallback is not used if the primary did not walk the stack at all,
+/// as is the case for nested captures.  Returns `true` if frames were left
+/// out due to the limit.
+#[cfg(feature = "fp-unwind")]
+fn trace_with_fallback(
+    rv: &mut Vec<Frame>,
+    max_frames: usize,
+    primary: fn(&mut Vec<Frame>, usize) -> (bool, bool),
+    fallback: fn(&mut Vec<Frame>, usize) -> bool,
+) -> bool {
+    let len = rv.len();
+    let (walked, truncated) = primary(rv, max_frames);
+    if walked && rv.len() == len {
+        return fallback(rv, max_frames);
+    }
+    truncated
+}
 
-                match pending_frame {
-                    None => {
-                        pending_frame = Some((ip, addr_hint, vec![symbol]));
-                    }
-                    Some((cur_ip, cur_addr_hint, ref mut symbols))
-                        if cur_ip == ip && cur_addr_hint == addr_hint =>
-                    {
-                        symbols.push(symbol);
-                    }
-                    Some((_, _, symbols)) => {
-                        if !symbols.is_empty() {
-                            rv.push(Frame::new_resolved(ip, addr_hint, symbols));
-                        }
-                        pending_frame = Some((ip, addr_hint, vec![symbol]));
-                    }
-                }
-            }
+/// The largest distance between two frame pointers that is considered valid.
+#[cfg(feature = "fp-unwind")]
+const MAX_FP_FRAME_SIZE: usize = 1024 * 1024;
 
-            if let Some((ip, addr_hint, symbols)) = pending_frame {
-                if !symbols.is_empty() {
-                    rv.push(Frame::new_resolved(ip, addr_hint, symbols));
-                }
-            }
+/// Appends the frames of the current stack by following frame pointers.
+///
+/// This is the fallback if the unwinder produced no frames.  It is only
+/// sound if all code on the stack is compiled with frame pointers
+/// (`-C force-frame-pointers=yes`), otherwise the frame pointer register
+/// holds arbitrary values.  Records are only read within the stack of the
+/// current thread, if its bounds are not known nothing is walked.  Returns
+/// `true` if frames were left out due to the limit.
+#[cfg(feature = "fp-unwind")]
+#[inline(never)]
+fn fp_trace(rv: &mut Vec<Frame>, max_frames: usize) -> bool {
+    let local = 0u8;
+    let stack = &local as *const u8 as usize;
+    match current_stack_bounds() {
+        // everything between this frame and the top of the stack is mapped
+        Some(bounds) if bounds.contains(&stack) => unsafe {
+            walk_frame_pointers(rv, current_frame_pointer(), stack..bounds.end, max_frames)
+        },
+        _ => false,
+    }
+}
 
-            Backtrace {
-                repr: BacktraceRepr::Frames(rv),
-            }
+/// Follows the chain of frame records starting at `fp`.
+///
+/// Records are only read if they lie within `stack`.  The stack grows
+/// downwards so callers have higher frame pointers, the walk stops at the
+/// first record that does not point further up.
+///
+/// # Safety
+///
+/// All of `stack` must be readable memory.  The addresses read are never
+/// outside of it, but whatever is found there is treated as a frame record.
+#[cfg(feature = "fp-unwind")]
+unsafe fn walk_frame_pointers(
+    rv: &mut Vec<Frame>,
+    mut fp: usize,
+    stack: std::ops::Range<usize>,
+    max_frames: usize,
+) -> bool {
+    let record_size = 2 * mem::size_of::<usize>();
+    let mut count = 0;
+    while fp >= stack.start
+        && fp < stack.end
+        && stack.end - fp >= record_size
+        && fp.is_multiple_of(mem::align_of::<usize>())
+    {
+        if count >= max_frames {
+            return true;
         }
-
-        #[cfg(not(feature = "backtrace"))]
-        {
-            self
+        // a frame record is the caller's frame pointer followed by the
+        // return address
+        let record = fp as *const usize;
+        let (next, ret) = (*record, *record.add(1));
+        if ret == 0 {
+            break;
         }
+        rv.push(Frame::new(ret as *mut c_void, AddrHint::Return));
+        count += 1;
+        if next <= fp || next - fp > MAX_FP_FRAME_SIZE {
+            break;
+        }
+        fp = next;
     }
+    false
+}
 
-    /// Iterates over the frames.
-    pub fn iter_frames<'a>(&'a self) -> FrameIter<'a> {
-        FrameIter {
-            bt: &self.repr,
-            #[cfg(feature = "backtrace")]
-            idx: 0,
+/// Returns the address range of the stack of the current thread.
+#[cfg(all(feature = "fp-unwind", target_os = "linux"))]
+fn current_stack_bounds() -> Option<std::ops::Range<usize>> {
+    use std::os::raw::c_int;
+
+    // large enough and aligned for `pthread_attr_t` of glibc and musl
+    #[repr(C, align(16))]
+    struct PthreadAttr([u8; 128]);
+
+    extern "C" {
+        fn pthread_self() -> usize;
+        fn pthread_getattr_np(thread: usize, attr: *mut PthreadAttr) -> c_int;
+        fn pthread_attr_getstack(
+            attr: *const PthreadAttr,
+            addr: *mut *mut c_void,
+            size: *mut usize,
+        ) -> c_int;
+        fn pthread_attr_destroy(attr: *mut PthreadAttr) -> c_int;
+    }
+
+    let mut attr = PthreadAttr([0; 128]);
+    let mut addr = ptr::null_mut();
+    let mut size = 0;
+    unsafe {
+        if pthread_getattr_np(pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let rv = pthread_attr_getstack(&attr, &mut addr, &mut size);
+        pthread_attr_destroy(&mut attr);
+        if rv != 0 || addr.is_null() {
+            return None;
         }
     }
+    let start = addr as usize;
+    Some(start..start.checked_add(size)?)
 }
 
-/// An iterator over all frames in a backtrace.
-pub struct FrameIter<'a> {
-    bt: &'a BacktraceRepr,
-    #[cfg(feature = "backtrace")]
-    idx: usize,
+/// The stack bounds are not known on this platform.
+#[cfg(all(feature = "fp-unwind", not(target_os = "linux")))]
+fn current_stack_bounds() -> Option<std::ops::Range<usize>> {
+    None
 }
 
-impl<'a> Iterator for FrameIter<'a> {
-    type Item = &'a Frame;
-
-    fn next(&mut self) -> Option<&'a Frame> {
-        match *self.bt {
-            BacktraceRepr::Empty => None,
-            #[cfg(feature = "backtrace")]
-            BacktraceRepr::Frames(ref frames) => match frames.get(self.idx) {
-                Some(frame) => {
-                    self.idx += 1;
-                    Some(frame)
-                }
-                None => None,
-            },
-        }
+#[cfg(all(feature = "fp-unwind", target_arch = "x86_64"))]
+#[inline(always)]
+fn current_frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        ::std::arch::asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack));
     }
+    fp
 }
 
-impl Default for Backtrace {
-    fn default() -> Backtrace {
-        Backtrace {
-            repr: BacktraceRepr::Empty,
-        }
+#[cfg(all(feature = "fp-unwind", target_arch = "aarch64"))]
+#[inline(always)]
+fn current_frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        ::std::arch::asm!("mov {}, x29", out(reg) fp, options(nomem, nostack));
     }
+    fp
+}
+
+/// Frame pointer walking is not implemented for this architecture.
+#[cfg(all(
+    feature = "fp-unwind",
+    not(any(target_arch = "x86_64", target_arch = "aarch64"))
+))]
+fn current_frame_pointer() -> usize {
+    0
 }
 
+/// Captures up to `max_frames` frames below the function at `marker` minus
+/// `skip` frames.
+///
+/// Until the marker is found the frames are only bounded by the global
+/// limit so that the frames of the capturing machinery do not count.  If
+/// the marker is not on the stack the frames are counted from the top.
 #[cfg(feature = "backtrace")]
-fn capture_backtrace(light: bool) -> Vec<Frame> {
-    let mut idx = 0;
-    let mut rv = vec![];
-    backtrace_support::trace(|frame| {
-        let hint = if idx == 0 {
-            AddrHint::Precise
-        } else {
-            AddrHint::Return
+fn capture_backtrace_after(marker: usize, skip: usize, max_frames: usize) -> (Vec<Frame>, bool) {
+    let max_frames = max_frames.min(max_backtrace_frames());
+    let mut truncated = false;
+    let mut frames = vec![];
+    let mut start: Option<usize> = None;
+    guarded_trace(|frame| {
+        let limit = match start {
+            Some(start) => start.saturating_add(skip).saturating_add(max_frames),
+            None => max_backtrace_frames(),
         };
-        rv.push(Frame::new(frame.ip(), hint));
-        idx += 1;
-        !light || idx < 3
+        if frames.len() >= limit {
+            truncated = true;
+            return false;
+        }
+        frames.push(Frame::from_traced(frame, AddrHint::Return));
+        if start.is_none() && frame.symbol_address() as usize == marker {
+            start = Some(frames.len());
+        }
+        true
     });
-    rv
+    frames.drain(..start.unwrap_or(0).saturating_add(skip).min(frames.len()));
+    if frames.len() > max_frames {
+        frames.truncate(max_frames);
+        truncated = true;
+    }
+    (frames, truncated)
 }
 
 impl fmt::Debug for Symbol {
@@ -521,24 +2799,87 @@ impl fmt::Debug for Symbol {
             .field("addr", &self.addr())
             .field("filename", &self.filename())
             .field("lineno", &self.lineno())
+            .field("column", &self.column())
             .finish()
     }
 }
 
 impl fmt::Display for Symbol {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(ref name) = self.name() {
-            fmt::Display::fmt(name, f)?;
-        } else {
-            write!(f, "?")?;
+        self.write(f, false, None, None)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+/// Escape codes used for colored output.
+const STYLE_NAME: &str = "\x1b[1m";
+const STYLE_LOCATION: &str = "\x1b[2m";
+const STYLE_RESET: &str = "\x1b[0m";
+
+impl Symbol {
+    /// The name as written by `write`, optionally with an offset.
+    fn display_name(&self, offset: Option<usize>) -> String {
+        let mut rv = match self.name() {
+            Some(name) => name.to_string(),
+            None => "?".to_string(),
+        };
+        if let Some(offset) = offset {
+            rv.push_str(&format!("+{:#x}", offset));
+        }
+        rv
+    }
+
+    /// Writes the symbol, padding the name to `name_width` if given.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        colored: bool,
+        name_width: Option<usize>,
+        offset: Option<usize>,
+    ) -> fmt::Result {
+        let name = self.display_name(offset);
+        if colored {
+            f.write_str(STYLE_NAME)?;
+        }
+        f.write_str(&name)?;
+        if colored {
+            f.write_str(STYLE_RESET)?;
         }
         let file = self.filename().and_then(|x| x.file_name().map(Path::new));
         let lineno = self.lineno();
-        match (file, lineno) {
-            (Some(file), Some(lineno)) => write!(f, " ({}:{})", file.display(), lineno)?,
-            (Some(file), None) => write!(f, " ({})", file.display())?,
+        if let (Some(width), Some(_)) = (name_width, file) {
+            let len = name.chars().count();
+            if len < width {
+                write!(f, "{: >1$}", "", width - len)?;
+            }
+        }
+        if colored && file.is_some() {
+            f.write_str(STYLE_LOCATION)?;
+        }
+        match (file, lineno, self.column()) {
+            (Some(file), Some(lineno), Some(column)) => {
+                write!(f, " ({}:{}:{})", file.display(), lineno, column)?
+            }
+            (Some(file), Some(lineno), None) => write!(f, " ({}:{})", file.display(), lineno)?,
+            (Some(file), None, _) => write!(f, " ({})", file.display())?,
             _ => {}
         }
+        if colored && file.is_some() {
+            f.write_str(STYLE_RESET)?;
+        }
         Ok(())
     }
 }
@@ -546,7 +2887,7 @@ impl fmt::Display for Symbol {
 impl fmt::Debug for Frame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Frame")
-            .field("ip", &self.ip)
+            .field("ip", &self.ip())
             .field("hint", &self.hint)
             .field("symbols", &self.symbols())
             .finish()
@@ -555,39 +2896,509 @@ impl fmt::Debug for Frame {
 
 impl fmt::Display for Frame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "  ")?;
-        if f.alternate() {
-            write!(f, "{: >14p} ", self.ip())?;
-        }
+        let name_width = if f.alternate() { f.width() } else { None };
+        self.write(f, false, name_width)
+    }
+}
+
+/// The widest name column the alternate formatting aligns to.
+const MAX_NAME_COLUMN: usize = 60;
+
+impl Frame {
+    /// Writes the frame.
+    ///
+    /// In the alternate form the instruction pointer takes up a fixed column
+    /// and continuation lines of inlined symbols are indented to match, the
+    /// outermost symbol also shows the offset into it.  If a name width is
+    /// given the locations are aligned after the names.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        colored: bool,
+        name_width: Option<usize>,
+    ) -> fmt::Result {
         for (idx, sym) in self.symbols().iter().enumerate() {
             if idx > 0 {
-                write!(f, "\n")?;
-                if f.alternate() {
+                writeln!(f)?;
+            }
+            write!(f, "  ")?;
+            if f.alternate() {
+                if idx == 0 {
+                    write!(f, "{: >14p} ", self.ip())?;
+                } else {
                     write!(f, "{: >14} ", "")?;
                 }
             }
-            write!(f, "in {}", sym)?;
+            write!(f, "in ")?;
+            sym.write(f, colored, name_width, self.display_offset(f, idx))?;
+            if idx < self.inline_count() {
+                write!(f, " [inlined]")?;
+            }
+        }
+        if self.symbols().is_empty() {
+            write!(f, "  ")?;
+            if f.alternate() {
+                write!(f, "{: >14p}", self.ip())?;
+            }
+        }
+        if self.repeat > 1 {
+            write!(f, " (×{})", self.repeat)?;
         }
         Ok(())
     }
+
+    /// The offset shown next to the symbol at `idx`.
+    fn display_offset(&self, f: &fmt::Formatter, idx: usize) -> Option<usize> {
+        if f.alternate() && idx == self.inline_count() {
+            self.offset_in_symbol()
+        } else {
+            None
+        }
+    }
 }
 
 impl fmt::Debug for Backtrace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let frames: Vec<_> = self.iter_frames().collect();
-        f.debug_struct("Backtrace")
-            .field("frames", &&frames[..])
-            .finish()
+        if f.alternate() {
+            let frames: Vec<_> = self.iter_frames().collect();
+            f.debug_struct("Backtrace")
+                .field("frames", &&frames[..])
+                .finish()
+        } else {
+            f.debug_struct("Backtrace")
+                .field("frames", &self.iter_frames().count())
+                .finish()
+        }
     }
 }
 
 impl fmt::Display for Backtrace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Backtrace (most recent call first):")?;
-        for frame in self.iter_frames() {
-            write!(f, "\n")?;
-            fmt::Display::fmt(frame, f)?;
+        self.write(f, false, f.precision())
+    }
+}
+
+impl Backtrace {
+    /// Writes the backtrace, showing at most `limit` frames if given.
+    fn write(&self, f: &mut fmt::Formatter, colored: bool, limit: Option<usize>) -> fmt::Result {
+        let name_width = if f.alternate() {
+            Some(self.name_column_width())
+        } else {
+            None
+        };
+        let limit = limit.unwrap_or(usize::MAX);
+        write!(f, "{}", BacktraceHeader(self))?;
+        for frame in self.iter_frames().take(limit) {
+            writeln!(f)?;
+            frame.write(f, colored, name_width)?;
+        }
+        let more = self.len().saturating_sub(limit);
+        if more > 0 {
+            write!(f, "\n  ... ({} more frames)", more)?;
+        } else if self.truncated {
+            write!(f, "\n  ... (truncated)")?;
+        }
+        Ok(())
+    }
+
+    /// The width of the name column in the alternate formatting.
+    fn name_column_width(&self) -> usize {
+        self.iter_frames()
+            .flat_map(|frame| {
+                let offset = frame.offset_in_symbol();
+                let inline_count = frame.inline_count();
+                frame.symbols().iter().enumerate().map(move |(idx, sym)| {
+                    let offset = if idx == inline_count { offset } else { None };
+                    sym.display_name(offset).chars().count()
+                })
+            })
+            .filter(|&x| x <= MAX_NAME_COLUMN)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct BacktraceHeader<'a>(&'a Backtrace);
+
+impl<'a> fmt::Display for BacktraceHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Backtrace (")?;
+        if let Some(name) = self.0.thread_name() {
+            write!(f, "thread {:?}, ", name)?;
+        }
+        if let Some(loc) = self.0.caller() {
+            write!(f, "captured at {}, ", loc)?;
+        }
+        write!(f, "most recent call first):")
+    }
+}
+
+struct ShortBacktrace {
+    backtrace: Backtrace,
+    n: usize,
+}
+
+impl fmt::Display for ShortBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.backtrace.write(f, false, Some(self.n))
+    }
+}
+
+#[cfg(feature = "color")]
+struct ColoredBacktrace<'a>(&'a Backtrace);
+
+#[cfg(feature = "color")]
+impl<'a> fmt::Display for ColoredBacktrace<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write(f, color_enabled(), f.precision())
+    }
+}
+
+/// Checks the `NO_COLOR` and `CLICOLOR` conventions.
+#[cfg(feature = "color")]
+fn color_enabled() -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty()) {
+        return false;
+    }
+    env::var_os("CLICOLOR").is_none_or(|x| x != "0")
+}
+
+struct CompactBacktrace<'a>(&'a Backtrace);
+
+impl<'a> fmt::Display for CompactBacktrace<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (_, _, sym)) in self.0.iter_symbols().enumerate() {
+            if idx > 0 {
+                write!(f, "<-")?;
+            }
+            match sym.name() {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "?")?,
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(feature = "sentry")]
+mod sentry_impl {
+    use serde_json::{Map, Value};
+
+    use super::{Backtrace, Symbol};
+
+    fn sentry_frame(ip: usize, sym: &Symbol) -> Value {
+        let mut frame = Map::new();
+        if let Some(name) = sym.name() {
+            if let Some(function) = name.demangled() {
+                frame.insert("function".into(), function.into());
+            }
+            frame.insert(
+                "symbol".into(),
+                String::from_utf8_lossy(name.as_bytes()).into(),
+            );
+        }
+        // paths are not necessarily utf-8, sentry only takes strings
+        if let Some(filename) = sym.filename() {
+            frame.insert("filename".into(), filename.to_string_lossy().into());
+        }
+        if let Some(lineno) = sym.lineno() {
+            frame.insert("lineno".into(), lineno.into());
+        }
+        if let Some(colno) = sym.column() {
+            frame.insert("colno".into(), colno.into());
+        }
+        frame.insert("instruction_addr".into(), format!("{:#x}", ip).into());
+        Value::Object(frame)
+    }
+
+    impl Backtrace {
+        /// Converts the backtrace into a Sentry stacktrace.
+        ///
+        /// This produces the `{"frames": [...]}` structure of the Sentry
+        /// protocol.  Every symbol becomes a frame and the frames are ordered
+        /// oldest call first as Sentry expects, which is the reverse of
+        /// `iter_symbols()`.  Demangled names go into `function`, raw names
+        /// into `symbol`.  Filenames that are not valid utf-8 are converted
+        /// lossily.
+        ///
+        /// This requires the `sentry` feature.
+        pub fn to_sentry_value(&self) -> Value {
+            let mut frames: Vec<_> = self
+                .iter_symbols()
+                .map(|(ip, _, sym)| sentry_frame(ip as usize, sym))
+                .collect();
+            frames.reverse();
+            let mut rv = Map::new();
+            rv.insert("frames".into(), Value::Array(frames));
+            Value::Object(rv)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::borrow::Cow;
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{intern_path, AddrHint, Backtrace, Frame, Symbol};
+
+    fn format_addr(addr: usize) -> String {
+        format!("{:#x}", addr)
+    }
+
+    fn parse_addr<E: ::serde::de::Error>(s: &str) -> Result<usize, E> {
+        let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+        usize::from_str_radix(digits, 16).map_err(|_| E::custom(format!("invalid address '{}'", s)))
+    }
+
+    #[derive(Serialize)]
+    struct SymbolSer<'a> {
+        name: Option<Cow<'a, str>>,
+        demangled: Option<String>,
+        addr: Option<String>,
+        filename: Option<&'a Path>,
+        lineno: Option<u32>,
+        column: Option<u32>,
+    }
+
+    #[derive(Default, Deserialize)]
+    #[serde(default)]
+    struct SymbolDe {
+        name: Option<String>,
+        addr: Option<String>,
+        filename: Option<PathBuf>,
+        lineno: Option<u32>,
+        column: Option<u32>,
+    }
+
+    impl Serialize for Symbol {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            SymbolSer {
+                name: self.name.as_ref().map(|x| String::from_utf8_lossy(x)),
+                demangled: self
+                    .name()
+                    .and_then(|x| x.demangled().map(|x| x.to_string())),
+                addr: self.addr.map(format_addr),
+                filename: self.filename(),
+                lineno: self.lineno,
+                column: self.column,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Symbol {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+            let data = SymbolDe::deserialize(deserializer)?;
+            Ok(Symbol {
+                name: data.name.map(|x| x.into_bytes()),
+                addr: match data.addr {
+                    Some(ref addr) => Some(parse_addr(addr)?),
+                    None => None,
+                },
+                filename: data.filename.map(|x| intern_path(&x)),
+                lineno: data.lineno,
+                column: data.column,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    struct FrameSer<'a> {
+        ip: String,
+        addr_hint: AddrHint,
+        symbols: &'a [Symbol],
+    }
+
+    #[derive(Deserialize)]
+    struct FrameDe {
+        ip: String,
+        addr_hint: AddrHint,
+        #[serde(default)]
+        symbols: Vec<Symbol>,
+    }
+
+    impl Serialize for Frame {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            FrameSer {
+                ip: format_addr(self.ip),
+                addr_hint: self.hint,
+                symbols: self.symbols(),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Frame {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Frame, D::Error> {
+            let data = FrameDe::deserialize(deserializer)?;
+            let ip = parse_addr::<D::Error>(&data.ip)? as *mut _;
+            Ok(Frame::new_resolved(ip, data.addr_hint, data.symbols))
+        }
+    }
+
+    #[derive(Serialize)]
+    struct BacktraceSer<'a> {
+        frames: Vec<&'a Frame>,
+        truncated: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thread_name: Option<&'a str>,
+    }
+
+    #[derive(Deserialize)]
+    struct BacktraceDe {
+        frames: Vec<Frame>,
+        #[serde(default)]
+        truncated: bool,
+        #[serde(default)]
+        thread_name: Option<String>,
+    }
+
+    impl Serialize for Backtrace {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            BacktraceSer {
+                frames: self.iter_frames().collect(),
+                truncated: self.truncated,
+                thread_name: self.thread_name(),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Backtrace {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Backtrace, D::Error> {
+            let data = BacktraceDe::deserialize(deserializer)?;
+            let mut rv = Backtrace::from_frames(data.frames);
+            rv.truncated = data.truncated;
+            if data.thread_name.is_some() {
+                rv.info_mut().thread_name = data.thread_name;
+            }
+            Ok(rv)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fp-unwind"))]
+mod tests {
+    use super::{current_stack_bounds, trace_with_fallback, walk_frame_pointers, AddrHint, Frame};
+
+    fn push_frame(rv: &mut Vec<Frame>, ip: usize) {
+        rv.push(Frame::new(ip as *mut _, AddrHint::Return));
+    }
+
+    #[test]
+    fn test_fallback_if_primary_finds_nothing() {
+        let mut rv = vec![];
+        let truncated = trace_with_fallback(
+            &mut rv,
+            10,
+            |_, _| (true, false),
+            |rv, max_frames| {
+                push_frame(rv, max_frames);
+                true
+            },
+        );
+        assert!(truncated);
+        assert_eq!(rv.len(), 1);
+        assert_eq!(rv[0].ip() as usize, 10);
+    }
+
+    #[test]
+    fn test_no_fallback_if_primary_finds_frames() {
+        let mut rv = vec![];
+        let truncated = trace_with_fallback(
+            &mut rv,
+            10,
+            |rv, _| {
+                push_frame(rv, 0x1000);
+                (true, false)
+            },
+            |_, _| panic!("fallback invoked"),
+        );
+        assert!(!truncated);
+        assert_eq!(rv.len(), 1);
+
+        // nested captures do not walk the stack and never fall back
+        trace_with_fallback(
+            &mut rv,
+            10,
+            |_, _| (false, false),
+            |_, _| panic!("fallback invoked"),
+        );
+        assert_eq!(rv.len(), 1);
+    }
+
+    #[test]
+    fn test_walk_frame_pointers() {
+        let mut records = [0usize; 6];
+        let word = std::mem::size_of::<usize>();
+        let records = records.as_mut_ptr();
+        let base = records as usize;
+        let stack = base..base + 6 * word;
+        // three records, the last one ends the chain
+        let chain = [base + 2 * word, 0x1000, base + 4 * word, 0x2000, 0, 0x3000];
+        for (idx, &value) in chain.iter().enumerate() {
+            unsafe { records.add(idx).write(value) };
+        }
+
+        let mut rv = vec![];
+        assert!(!unsafe { walk_frame_pointers(&mut rv, base, stack.clone(), 10) });
+        let ips: Vec<_> = rv.iter().map(|x| x.ip() as usize).collect();
+        assert_eq!(ips, vec![0x1000, 0x2000, 0x3000]);
+
+        let mut rv = vec![];
+        assert!(unsafe { walk_frame_pointers(&mut rv, base, stack.clone(), 2) });
+        assert_eq!(rv.len(), 2);
+
+        // records outside of the stack are not read, even if chained
+        let mut rv = vec![];
+        unsafe { walk_frame_pointers(&mut rv, base, base..base + 4 * word, 10) };
+        assert_eq!(rv.len(), 2);
+        let mut rv = vec![];
+        unsafe { walk_frame_pointers(&mut rv, base, base..base + 3 * word, 10) };
+        assert_eq!(rv.len(), 1);
+        let mut rv = vec![];
+        unsafe { walk_frame_pointers(&mut rv, base, base + word..stack.end, 10) };
+        assert!(rv.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_current_stack_bounds() {
+        let local = 0u8;
+        let addr = &local as *const u8 as usize;
+        assert!(current_stack_bounds().unwrap().contains(&addr));
+        let addr = std::thread::spawn(|| {
+            let local = 0u8;
+            let addr = &local as *const u8 as usize;
+            assert!(current_stack_bounds().unwrap().contains(&addr));
+            addr
+        })
+        .join()
+        .unwrap();
+        assert!(!current_stack_bounds().unwrap().contains(&addr));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_module_base_from_unwinder() {
+        let mut frame = Frame::new(push_frame as *mut _, AddrHint::Precise);
+        frame.module_base_addr = Some(0x1000);
+        assert_eq!(frame.module_base(), Some(0x1000 as *mut _));
+        assert!(frame.module_name().is_some());
+    }
+
+    #[test]
+    fn test_walk_frame_pointers_rejects_bogus_pointers() {
+        let local = 0u8;
+        let stack = &local as *const u8 as usize;
+        let mut rv = vec![];
+        // none of these are dereferenced
+        for &fp in &[0, stack - 64, stack + (1 << 30), 8, stack + 1] {
+            unsafe { walk_frame_pointers(&mut rv, fp, stack..stack + 1, 10) };
+        }
+        assert!(rv.is_empty());
+    }
+}
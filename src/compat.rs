@@ -0,0 +1,111 @@
+use std::error;
+use std::fmt;
+
+use traits::Error;
+
+/// Adapts a `defeat::Error` to the standard library's error trait.
+///
+/// This makes it possible to pass errors to APIs that expect a
+/// `std::error::Error`.  The origins of the error are exposed as `source()`.
+/// Because the standard library cannot borrow them as `defeat` errors, the
+/// origins are snapshotted as their `Display` and `Debug` output when the
+/// adapter is created.
+pub struct StdErrorAdapter<E: ?Sized> {
+    error: Box<E>,
+    source: Option<Box<OriginSnapshot>>,
+}
+
+impl<E: Error + ?Sized> StdErrorAdapter<E> {
+    /// Wraps a boxed error.
+    pub fn new(error: Box<E>) -> StdErrorAdapter<E> {
+        let source = OriginSnapshot::chain(error.origin());
+        StdErrorAdapter { error, source }
+    }
+
+    /// Returns a reference to the wrapped error.
+    pub fn get_ref(&self) -> &E {
+        &self.error
+    }
+
+    /// Unwraps the adapter and returns the wrapped error.
+    pub fn into_inner(self) -> Box<E> {
+        self.error
+    }
+}
+
+#[cfg(feature = "anyhow")]
+impl<E: Error + Send + Sync + ?Sized + 'static> StdErrorAdapter<E> {
+    /// Converts the adapter into an `anyhow::Error`.
+    ///
+    /// The origins stay reachable through `anyhow`'s chain.
+    pub fn into_anyhow(self) -> anyhow::Error {
+        anyhow::Error::new(self)
+    }
+}
+
+impl<E: Error + ?Sized> fmt::Debug for StdErrorAdapter<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.error, f)
+    }
+}
+
+impl<E: Error + ?Sized> fmt::Display for StdErrorAdapter<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<E: Error + ?Sized> error::Error for StdErrorAdapter<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source.as_ref().map(|x| &**x as &_)
+    }
+}
+
+/// Allows `?` to turn `defeat` errors into `anyhow::Error`.
+#[cfg(feature = "anyhow")]
+impl From<Box<dyn Error + Send + Sync>> for anyhow::Error {
+    fn from(err: Box<dyn Error + Send + Sync>) -> anyhow::Error {
+        StdErrorAdapter::new(err).into_anyhow()
+    }
+}
+
+/// A snapshot of an origin in the error chain.
+struct OriginSnapshot {
+    display: String,
+    debug: String,
+    source: Option<Box<OriginSnapshot>>,
+}
+
+impl OriginSnapshot {
+    fn chain(origin: Option<&(dyn Error + 'static)>) -> Option<Box<OriginSnapshot>> {
+        let links: Vec<_> = match origin {
+            Some(origin) => origin.iter_chain().collect(),
+            None => return None,
+        };
+        links.into_iter().rev().fold(None, |source, link| {
+            Some(Box::new(OriginSnapshot {
+                display: link.to_string(),
+                debug: format!("{:?}", link),
+                source,
+            }))
+        })
+    }
+}
+
+impl fmt::Debug for OriginSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.debug)
+    }
+}
+
+impl fmt::Display for OriginSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.display)
+    }
+}
+
+impl error::Error for OriginSnapshot {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source.as_ref().map(|x| &**x as &_)
+    }
+}
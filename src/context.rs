@@ -0,0 +1,83 @@
+use std::fmt;
+
+use backtrace::{Backtrace, CapturePurpose};
+use traits::Error;
+
+/// An error that wraps another error with a human readable message.
+///
+/// The wrapped error becomes the origin of the context error.  A backtrace
+/// is captured when the context is attached if backtraces for errors are
+/// enabled.
+pub struct Context<E> {
+    msg: String,
+    error: E,
+    backtrace: Option<Backtrace>,
+}
+
+impl<E> Context<E> {
+    /// Wraps an error with a context message.
+    pub fn new<D: fmt::Display>(msg: D, error: E) -> Context<E> {
+        Context {
+            msg: msg.to_string(),
+            error,
+            backtrace: Backtrace::conditional_capture(CapturePurpose::Error),
+        }
+    }
+
+    /// Returns the context message.
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// Returns a reference to the wrapped error.
+    pub fn get_ref(&self) -> &E {
+        &self.error
+    }
+
+    /// Unwraps the context and returns the wrapped error.
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for Context<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("msg", &self.msg)
+            .field("error", &self.error)
+            .field("backtrace", &self.backtrace)
+            .finish()
+    }
+}
+
+impl<E> fmt::Display for Context<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.msg, f)
+    }
+}
+
+impl<E: Error + Send + Sync + 'static> Error for Context<E> {
+    fn sync_origin(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        Some(&self.error)
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        self.backtrace = Some(bt);
+    }
+}
+
+/// Extension methods for results to attach context to errors.
+pub trait ResultExt<T, E> {
+    /// Wraps the error of the result in a `Context` with the given message.
+    fn context<D: fmt::Display>(self, msg: D) -> Result<T, Context<E>>;
+}
+
+impl<T, E: Error + Send + Sync + 'static> ResultExt<T, E> for Result<T, E> {
+    fn context<D: fmt::Display>(self, msg: D) -> Result<T, Context<E>> {
+        self.map_err(|err| Context::new(msg, err))
+    }
+}
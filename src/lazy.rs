@@ -0,0 +1,71 @@
+use std::cell::OnceCell;
+use std::fmt;
+
+use backtrace::{Backtrace, CapturePurpose};
+use traits::Error;
+
+/// An error with a message whose origin is computed on demand.
+///
+/// The closure producing the origin runs the first time the origin is
+/// requested and its result is cached, so it runs at most once.  This is
+/// useful if constructing the cause is expensive and only needed when the
+/// error is inspected.  A backtrace is captured on construction if
+/// backtraces for errors are enabled.
+pub struct LazyError<F> {
+    msg: String,
+    make_origin: F,
+    origin: OnceCell<Box<dyn Error>>,
+    backtrace: Option<Backtrace>,
+}
+
+impl<F: Fn() -> Box<dyn Error>> LazyError<F> {
+    /// Creates a new lazy error.
+    pub fn new<D: fmt::Display>(msg: D, make_origin: F) -> LazyError<F> {
+        LazyError {
+            msg: msg.to_string(),
+            make_origin,
+            origin: OnceCell::new(),
+            backtrace: Backtrace::conditional_capture(CapturePurpose::Error),
+        }
+    }
+
+    /// Returns the message.
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// Returns `true` if the origin was already computed.
+    pub fn is_origin_computed(&self) -> bool {
+        self.origin.get().is_some()
+    }
+}
+
+impl<F> fmt::Debug for LazyError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyError")
+            .field("msg", &self.msg)
+            .field("origin", &self.origin.get())
+            .field("backtrace", &self.backtrace)
+            .finish()
+    }
+}
+
+impl<F> fmt::Display for LazyError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.msg, f)
+    }
+}
+
+impl<F: Fn() -> Box<dyn Error>> Error for LazyError<F> {
+    fn origin(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&**self.origin.get_or_init(|| (self.make_origin)()))
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        self.backtrace = Some(bt);
+    }
+}
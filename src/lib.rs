@@ -1,8 +1,28 @@
+#[cfg(feature = "anyhow")]
+extern crate anyhow;
 #[cfg(feature = "backtrace")]
 extern crate backtrace as backtrace_support;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "sentry")]
+extern crate serde_json;
 
 mod backtrace;
+mod compat;
+mod context;
+mod lazy;
+mod message;
+mod panic;
 mod traits;
 
-pub use backtrace::{AddrHint, Backtrace, CapturePurpose, Frame, FrameIter, Symbol, SymbolName};
-pub use traits::Error;
+pub use backtrace::{
+    set_backtrace_strategy, set_max_backtrace_frames, AddrHint, Backtrace, BacktraceDiff,
+    BacktraceStrategy, CapturePurpose, Frame, FrameIter, ParseError, ResolutionState, Symbol,
+    SymbolIter, SymbolName, TrimOptions,
+};
+pub use compat::StdErrorAdapter;
+pub use context::{Context, ResultExt};
+pub use lazy::LazyError;
+pub use message::Message;
+pub use panic::install_panic_hook;
+pub use traits::{Error, ErrorChainIter};
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use backtrace::{Backtrace, CapturePurpose};
+use traits::Error;
+
+/// A simple error carrying only a message.
+///
+/// This is useful for ad-hoc errors that do not warrant their own type.  A
+/// backtrace is captured on construction if backtraces for errors are
+/// enabled.  Messages can also be created from strings with `From`, so
+/// `Err(Message::from("..."))?` works in functions returning boxed errors.
+pub struct Message {
+    msg: String,
+    backtrace: Option<Backtrace>,
+}
+
+impl Message {
+    /// Creates a new message error.
+    pub fn new<D: fmt::Display>(msg: D) -> Message {
+        Message {
+            msg: msg.to_string(),
+            backtrace: Backtrace::conditional_capture(CapturePurpose::Error),
+        }
+    }
+
+    /// Returns the message.
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl From<String> for Message {
+    fn from(msg: String) -> Message {
+        Message {
+            msg,
+            backtrace: Backtrace::conditional_capture(CapturePurpose::Error),
+        }
+    }
+}
+
+impl From<&'static str> for Message {
+    fn from(msg: &'static str) -> Message {
+        Message::from(msg.to_string())
+    }
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Message")
+            .field("msg", &self.msg)
+            .field("backtrace", &self.backtrace)
+            .finish()
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.msg, f)
+    }
+}
+
+impl Error for Message {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        self.backtrace = Some(bt);
+    }
+}
+
+/// Creates a `Message` error from a format string.
+///
+/// This accepts the same arguments as `format!`.
+#[macro_export]
+macro_rules! format_err {
+    ($($arg:tt)*) => {
+        $crate::Message::new(format!($($arg)*))
+    };
+}
@@ -0,0 +1,90 @@
+use std::cell::Cell;
+use std::env;
+use std::panic;
+use std::sync::Once;
+
+use backtrace::{Backtrace, CapturePurpose, Frame};
+
+static INSTALL_PANIC_HOOK: Once = Once::new();
+
+/// Installs a panic hook that prints a `defeat` backtrace.
+///
+/// The backtrace is captured with `CapturePurpose::Panic` and printed
+/// trimmed to stderr after the previously installed hook ran.  As the
+/// default hook of the standard library already prints a backtrace if
+/// `RUST_BACKTRACE` is set, this hook only prints one if
+/// `RUST_PANIC_BACKTRACE` is set explicitly.  Set only the latter to get
+/// the `defeat` formatting instead of the one of the standard library.
+///
+/// Calling this more than once has no effect.
+pub fn install_panic_hook() {
+    INSTALL_PANIC_HOOK.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            prev(info);
+            if env::var_os("RUST_PANIC_BACKTRACE").is_none() {
+                return;
+            }
+            if let Some(bt) = Backtrace::conditional_capture(CapturePurpose::Panic) {
+                if bt.was_full_requested() {
+                    eprintln!("{}", bt);
+                } else {
+                    eprintln!("{}", trim_panic_frames(bt));
+                }
+            }
+        }));
+    });
+}
+
+/// Checks if one of the functions in the frame has the given name.
+///
+/// Only the last path segment is compared, so this does not depend on the
+/// private module of the standard library a function lives in.
+fn has_function(frame: &Frame, name: &str) -> bool {
+    frame.symbols().iter().any(|symbol| {
+        symbol
+            .name()
+            .and_then(|x| x.without_generics())
+            .is_some_and(|x| x == name || x.ends_with(&format!("::{}", name)))
+    })
+}
+
+/// Checks if the frame is the entry point into panicking.
+fn is_panic_entry(frame: &Frame) -> bool {
+    has_function(frame, "rust_begin_unwind")
+        || has_function(frame, "begin_panic")
+        || frame
+            .symbol_name()
+            .is_some_and(|x| x.starts_with("core::panicking::"))
+}
+
+/// Trims the backtrace of a panic like the standard library does.
+///
+/// The frames up to `__rust_end_short_backtrace` belong to the panic
+/// machinery, the entry points right below it are skipped as well.  The
+/// frames from `__rust_begin_short_backtrace` on belong to the runtime.  If
+/// the markers cannot be found only the regular trimming applies.
+fn trim_panic_frames(bt: Backtrace) -> Backtrace {
+    let frames = bt.frames();
+    let mut start = frames
+        .iter()
+        .position(|x| has_function(x, "__rust_end_short_backtrace"))
+        .map_or(0, |idx| idx + 1);
+    if start > 0 {
+        while start < frames.len() && is_panic_entry(&frames[start]) {
+            start += 1;
+        }
+    }
+    let end = frames[start..]
+        .iter()
+        .position(|x| has_function(x, "__rust_begin_short_backtrace"))
+        .map_or(frames.len(), |idx| start + idx);
+
+    let idx = Cell::new(0);
+    bt.filter_frames(|_| {
+        let rv = idx.get() >= start && idx.get() < end;
+        idx.set(idx.get() + 1);
+        rv
+    })
+    .trimmed()
+}
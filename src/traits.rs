@@ -1,14 +1,35 @@
 use std::any::TypeId;
 use std::error;
-use std::fmt::{Debug, Display};
+use std::fmt::{self, Debug, Display, Write};
 
-use backtrace::Backtrace;
+use backtrace::{Backtrace, CapturePurpose};
+
+/// The maximum number of links the chain walking helpers visit.
+///
+/// This guards against cyclic chains that would otherwise never end.  It
+/// applies to `iter_chain` (and thus `root_cause` and `find_cause`),
+/// `context_chain`, `chain_is_transient` and `chain_backtrace`.
+const MAX_CHAIN_DEPTH: usize = 64;
 
 /// An error trait
+///
+/// Implementations usually only provide `sync_origin` and `backtrace`, all
+/// other methods have defaults derived from these.  Code generating impls,
+/// such as a derive, relies on this contract:
+///
+/// * the field holding the wrapped error is returned from `sync_origin`.
+///   `origin` defaults to the same error so it must only be overridden for
+///   origins that are not `Send` and `Sync`.
+/// * a field named `backtrace` of type `Backtrace` or `Option<Backtrace>` is
+///   returned from `backtrace` and replaced by `set_backtrace`.
+/// * `Display` and `Debug` are implemented separately, the trait itself
+///   never formats the error.
+///
+/// See `examples/error_impl.rs` for a hand written impl following it.
 pub trait Error: Debug + Display {
     /// Returns the origin of this error which can be another error.
     fn origin(&self) -> Option<&(dyn Error + 'static)> {
-        self.sync_origin().map(|x| &*x as &_)
+        self.sync_origin().map(|x| x as &_)
     }
 
     /// Like `origin` but only returns an error if it's sync and send.
@@ -21,6 +42,101 @@ pub trait Error: Debug + Display {
         None
     }
 
+    /// Returns the first backtrace found in the chain of errors.
+    ///
+    /// The backtrace of this error is returned if it has one, otherwise the
+    /// origins are searched.  This finds the backtrace regardless of how
+    /// many times the error carrying it was wrapped.
+    fn chain_backtrace(&self) -> Option<&Backtrace> {
+        if let Some(bt) = self.backtrace() {
+            return Some(bt);
+        }
+        let mut next = self.origin();
+        let mut depth = 1;
+        while let Some(err) = next {
+            if depth >= MAX_CHAIN_DEPTH {
+                break;
+            }
+            if let Some(bt) = err.backtrace() {
+                return Some(bt);
+            }
+            next = err.origin();
+            depth += 1;
+        }
+        None
+    }
+
+    /// Attaches a backtrace to the error.
+    ///
+    /// Types that carry a backtrace override this to store it so that it is
+    /// returned from `backtrace` afterwards, for instance to attach one at
+    /// the site an error was caught if it has none.  By default the
+    /// backtrace is discarded.
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        let _ = bt;
+    }
+
+    /// Returns the backtrace of the error or captures one.
+    ///
+    /// If the error carries no backtrace one is captured as if for a new
+    /// error.  Note that such a fallback shows where this method was called
+    /// (for instance a logging site) and not where the error originated.
+    fn ensure_backtrace(&self) -> Option<Backtrace> {
+        match self.backtrace() {
+            Some(bt) => Some(bt.clone()),
+            None => Backtrace::conditional_capture(CapturePurpose::Error),
+        }
+    }
+
+    /// Returns `true` if retrying the failed operation might succeed.
+    ///
+    /// Errors are not transient by default, types that know better (for
+    /// instance timeouts) can override this.
+    fn is_transient(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` if this error or any of its origins is transient.
+    fn chain_is_transient(&self) -> bool {
+        if self.is_transient() {
+            return true;
+        }
+        let mut next = self.origin();
+        let mut depth = 1;
+        while let Some(err) = next {
+            if depth >= MAX_CHAIN_DEPTH {
+                break;
+            }
+            if err.is_transient() {
+                return true;
+            }
+            next = err.origin();
+            depth += 1;
+        }
+        false
+    }
+
+    /// Renders the error and all of its origins into a single line.
+    ///
+    /// The `Display` output of every link is joined with `": "`, for
+    /// instance `failed to start: connection refused`.  At most 64 links are
+    /// rendered, longer (or cyclic) chains end in `...`.
+    fn context_chain(&self) -> String {
+        let mut rv = self.to_string();
+        let mut next = self.origin();
+        let mut depth = 1;
+        while let Some(err) = next {
+            if depth >= MAX_CHAIN_DEPTH {
+                rv.push_str(": ...");
+                break;
+            }
+            write!(rv, ": {}", err).unwrap();
+            next = err.origin();
+            depth += 1;
+        }
+        rv
+    }
+
     /// Get the `TypeId` of `self`
     #[doc(hidden)]
     fn type_id(&self) -> TypeId
@@ -39,11 +155,11 @@ pub trait Error: Debug + Display {
 
     #[doc(hidden)]
     fn cause(&self) -> Option<&dyn Error> {
-        self.origin().map(|x| &*x as &_)
+        self.origin().map(|x| x as &_)
     }
 }
 
-impl Error + 'static {
+impl dyn Error + 'static {
     /// Returns true if the boxed type is the same as `T`
     #[inline]
     pub fn is<T: Error + 'static>(&self) -> bool {
@@ -57,7 +173,7 @@ impl Error + 'static {
     #[inline]
     pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
         if self.is::<T>() {
-            unsafe { Some(&*(self as *const Error as *const T)) }
+            unsafe { Some(&*(self as *const dyn Error as *const T)) }
         } else {
             None
         }
@@ -68,51 +184,248 @@ impl Error + 'static {
     #[inline]
     pub fn downcast_mut<T: Error + 'static>(&mut self) -> Option<&mut T> {
         if self.is::<T>() {
-            unsafe { Some(&mut *(self as *mut Error as *mut T)) }
+            unsafe { Some(&mut *(self as *mut dyn Error as *mut T)) }
         } else {
             None
         }
     }
+
+    /// Attempts to downcast the box to a concrete type.
+    ///
+    /// If the error is not of type `T` the box is returned unchanged.
+    #[inline]
+    pub fn downcast<T: Error + 'static>(self: Box<Self>) -> Result<Box<T>, Box<dyn Error>> {
+        if self.is::<T>() {
+            unsafe { Ok(Box::from_raw(Box::into_raw(self) as *mut T)) }
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Iterates over this error and all of its origins.
+    ///
+    /// The first item yielded is the error itself, followed by each
+    /// successive value of `origin()`.  At most 64 errors are yielded so
+    /// that cyclic chains terminate.
+    #[inline]
+    pub fn iter_chain(&self) -> ErrorChainIter<'_> {
+        ErrorChainIter {
+            next: Some(self),
+            depth: 0,
+        }
+    }
+
+    /// Returns the deepest error in the chain.
+    ///
+    /// If the error has no origin this returns the error itself.  For chains
+    /// longer than what `iter_chain` walks this is the last error visited.
+    #[inline]
+    pub fn root_cause(&self) -> &(dyn Error + 'static) {
+        self.iter_chain().last().unwrap_or(self)
+    }
+
+    /// Returns the first error in the chain that is of type `T`.
+    ///
+    /// This considers the error itself as well as all of its origins.
+    #[inline]
+    pub fn find_cause<T: Error + 'static>(&self) -> Option<&T> {
+        self.iter_chain().find_map(|x| x.downcast_ref::<T>())
+    }
+
+    /// Returns `true` if the chain contains an error of type `T`.
+    ///
+    /// Like `find_cause` this considers the error itself as well.
+    #[inline]
+    pub fn caused_by<T: Error + 'static>(&self) -> bool {
+        self.find_cause::<T>().is_some()
+    }
+
+    /// Returns a value that displays the error with all of its origins.
+    ///
+    /// The error is printed as `Error: ...` followed by a numbered
+    /// `Caused by:` section listing the origins.  If any error in the chain
+    /// carries a backtrace the one of the deepest such error is appended.
+    /// This is useful for reporting errors at the top level of a program.
+    pub fn display_chain(&self) -> impl Display + '_ {
+        DisplayChain(self)
+    }
 }
 
-impl Error + 'static + Send {
+impl dyn Error + 'static + Send {
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn is<T: Error + 'static>(&self) -> bool {
-        <Error + 'static>::is::<T>(self)
+        <dyn Error + 'static>::is::<T>(self)
     }
 
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
-        <Error + 'static>::downcast_ref::<T>(self)
+        <dyn Error + 'static>::downcast_ref::<T>(self)
     }
 
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn downcast_mut<T: Error + 'static>(&mut self) -> Option<&mut T> {
-        <Error + 'static>::downcast_mut::<T>(self)
+        <dyn Error + 'static>::downcast_mut::<T>(self)
+    }
+
+    /// Like the method defined on the type `dyn Error` but keeps the bounds
+    /// of the box.
+    #[inline]
+    pub fn downcast<T: Error + 'static>(
+        self: Box<Self>,
+    ) -> Result<Box<T>, Box<dyn Error + 'static + Send>> {
+        if self.is::<T>() {
+            unsafe { Ok(Box::from_raw(Box::into_raw(self) as *mut T)) }
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn iter_chain(&self) -> ErrorChainIter<'_> {
+        <dyn Error + 'static>::iter_chain(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn root_cause(&self) -> &(dyn Error + 'static) {
+        <dyn Error + 'static>::root_cause(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn find_cause<T: Error + 'static>(&self) -> Option<&T> {
+        <dyn Error + 'static>::find_cause::<T>(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn caused_by<T: Error + 'static>(&self) -> bool {
+        <dyn Error + 'static>::caused_by::<T>(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    pub fn display_chain(&self) -> impl Display + '_ {
+        <dyn Error + 'static>::display_chain(self)
     }
 }
 
-impl Error + 'static + Send + Sync {
+impl dyn Error + 'static + Send + Sync {
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn is<T: Error + 'static>(&self) -> bool {
-        <Error + 'static>::is::<T>(self)
+        <dyn Error + 'static>::is::<T>(self)
     }
 
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
-        <Error + 'static>::downcast_ref::<T>(self)
+        <dyn Error + 'static>::downcast_ref::<T>(self)
     }
 
     /// Forwards to the method defined on the type `Any`.
     #[inline]
     pub fn downcast_mut<T: Error + 'static>(&mut self) -> Option<&mut T> {
-        <Error + 'static>::downcast_mut::<T>(self)
+        <dyn Error + 'static>::downcast_mut::<T>(self)
+    }
+
+    /// Like the method defined on the type `dyn Error` but keeps the bounds
+    /// of the box.
+    #[inline]
+    pub fn downcast<T: Error + 'static>(
+        self: Box<Self>,
+    ) -> Result<Box<T>, Box<dyn Error + 'static + Send + Sync>> {
+        if self.is::<T>() {
+            unsafe { Ok(Box::from_raw(Box::into_raw(self) as *mut T)) }
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn iter_chain(&self) -> ErrorChainIter<'_> {
+        <dyn Error + 'static>::iter_chain(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn root_cause(&self) -> &(dyn Error + 'static) {
+        <dyn Error + 'static>::root_cause(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn find_cause<T: Error + 'static>(&self) -> Option<&T> {
+        <dyn Error + 'static>::find_cause::<T>(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    #[inline]
+    pub fn caused_by<T: Error + 'static>(&self) -> bool {
+        <dyn Error + 'static>::caused_by::<T>(self)
+    }
+
+    /// Forwards to the method defined on the type `dyn Error`.
+    pub fn display_chain(&self) -> impl Display + '_ {
+        <dyn Error + 'static>::display_chain(self)
     }
 }
 
 impl<T: error::Error> Error for T {}
+
+/// An iterator over an error and its origins.
+pub struct ErrorChainIter<'a> {
+    next: Option<&'a (dyn Error + 'static)>,
+    depth: usize,
+}
+
+impl<'a> Iterator for ErrorChainIter<'a> {
+    type Item = &'a (dyn Error + 'static);
+
+    fn next(&mut self) -> Option<&'a (dyn Error + 'static)> {
+        if self.depth >= MAX_CHAIN_DEPTH {
+            return None;
+        }
+        let rv = self.next?;
+        self.next = rv.origin();
+        self.depth += 1;
+        Some(rv)
+    }
+}
+
+struct DisplayChain<'a>(&'a (dyn Error + 'static));
+
+impl<'a> Display for DisplayChain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error: {}", self.0)?;
+        for (idx, err) in self.0.iter_chain().skip(1).enumerate() {
+            if idx == 0 {
+                write!(f, "\n\nCaused by:")?;
+            }
+            write!(f, "\n    {}: {}", idx, err)?;
+        }
+        let bt = self.0.iter_chain().filter_map(|x| x.backtrace()).last();
+        if let Some(bt) = bt {
+            // forward the flags so that `{:#}` and `{:.N}` apply
+            write!(f, "\n\n")?;
+            Display::fmt(bt, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, E: Error + Send + Sync + 'a> From<E> for Box<dyn Error + Send + Sync + 'a> {
+    fn from(err: E) -> Box<dyn Error + Send + Sync + 'a> {
+        Box::new(err)
+    }
+}
+
+impl<'a, E: Error + 'a> From<E> for Box<dyn Error + 'a> {
+    fn from(err: E) -> Box<dyn Error + 'a> {
+        Box::new(err)
+    }
+}
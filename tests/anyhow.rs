@@ -0,0 +1,54 @@
+#![cfg(feature = "anyhow")]
+
+extern crate anyhow;
+extern crate defeat;
+
+use std::io;
+
+use defeat::{Error, ResultExt, StdErrorAdapter};
+
+fn read_config() -> Result<(), io::Error> {
+    Err(io::Error::other("disk full"))
+}
+
+fn load() -> Result<(), Box<dyn Error + Send + Sync>> {
+    read_config()
+        .context("could not read config")
+        .context("could not start")?;
+    Ok(())
+}
+
+fn run() -> anyhow::Result<()> {
+    load()?;
+    Ok(())
+}
+
+#[test]
+fn test_question_mark() {
+    let err = run().unwrap_err();
+    assert_eq!(err.to_string(), "could not start");
+    let chain: Vec<_> = err.chain().map(|x| x.to_string()).collect();
+    assert_eq!(
+        chain,
+        vec!["could not start", "could not read config", "disk full"]
+    );
+    assert_eq!(err.root_cause().to_string(), "disk full");
+    let source = err.source().unwrap();
+    assert_eq!(source.to_string(), "could not read config");
+    assert_eq!(source.source().unwrap().to_string(), "disk full");
+
+    // the defeat error is still reachable
+    let adapter = err
+        .downcast_ref::<StdErrorAdapter<dyn Error + Send + Sync>>()
+        .unwrap();
+    assert_eq!(adapter.get_ref().to_string(), "could not start");
+}
+
+#[test]
+fn test_into_anyhow() {
+    let err: Box<dyn Error + Send + Sync> = Box::new(io::Error::other("timeout"));
+    let err = StdErrorAdapter::new(err).into_anyhow();
+    assert_eq!(err.to_string(), "timeout");
+    assert!(err.source().is_none());
+    assert_eq!(err.chain().count(), 1);
+}
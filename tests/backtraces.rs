@@ -0,0 +1,1745 @@
+#![cfg(feature = "backtrace")]
+
+extern crate defeat;
+
+mod support;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use defeat::{
+    AddrHint, Backtrace, CapturePurpose, Frame, ResolutionState, Symbol, SymbolName, TrimOptions,
+};
+
+use support::with_env;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_send_sync() {
+    assert_send_sync::<Backtrace>();
+    assert_send_sync::<Frame>();
+    assert_send_sync::<Symbol>();
+    assert_send_sync::<SymbolName<'static>>();
+}
+
+#[test]
+fn test_resolve_from_threads() {
+    let bt = Arc::new(Backtrace::capture().unwrap());
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let bt = bt.clone();
+            thread::spawn(move || {
+                bt.iter_frames()
+                    .map(|frame| frame.symbols().len())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let counts: Vec<_> = handles.into_iter().map(|x| x.join().unwrap()).collect();
+    assert!(counts.windows(2).all(|x| x[0] == x[1]));
+    assert_eq!(counts[0].len(), bt.len());
+}
+
+fn sym(name: &str) -> Symbol {
+    Symbol::new(Some(name.as_bytes().to_vec()), None, None, None)
+}
+
+#[test]
+fn test_trimmed_keeps_frame_addresses() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Precise,
+            vec![sym("app::inlined"), sym("app::outer")],
+        ),
+        Frame::new_resolved(0x2000 as *mut _, AddrHint::Return, vec![sym("app::caller")]),
+        Frame::new_resolved(0x3000 as *mut _, AddrHint::Return, vec![sym("app::main")]),
+    ])
+    .trimmed();
+    let frames: Vec<_> = bt
+        .iter_frames()
+        .map(|frame| {
+            let names: Vec<_> = frame
+                .symbols()
+                .iter()
+                .map(|x| x.name().unwrap().to_string())
+                .collect();
+            (frame.ip() as usize, frame.addr_hint(), names)
+        })
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            (
+                0x1000,
+                AddrHint::Precise,
+                vec!["app::inlined".to_string(), "app::outer".to_string()]
+            ),
+            (0x2000, AddrHint::Return, vec!["app::caller".to_string()]),
+            (0x3000, AddrHint::Return, vec!["app::main".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn test_from_frames() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Precise,
+            vec![Symbol::new(
+                Some(b"app::handler".to_vec()),
+                None,
+                Some(PathBuf::from("/src/app/handler.rs")),
+                Some(42),
+            )],
+        ),
+        Frame::new(0x2000 as *mut _, AddrHint::Return),
+    ]);
+    assert_eq!(bt.len(), 2);
+    assert!(!bt.is_truncated());
+    assert_eq!(
+        bt.to_string(),
+        "Backtrace (most recent call first):\n  in app::handler (handler.rs:42)\n  "
+    );
+    let trimmed = bt.trimmed();
+    assert_eq!(trimmed.len(), 1);
+    assert_eq!(
+        trimmed.to_string(),
+        "Backtrace (most recent call first):\n  in app::handler (handler.rs:42)"
+    );
+}
+
+#[test]
+fn test_clone() {
+    let bt = Backtrace::capture().unwrap();
+    bt.resolve();
+    let cloned = bt.clone();
+    assert_eq!(format!("{:#}", cloned), format!("{:#}", bt));
+    assert_eq!(cloned.len(), bt.len());
+
+    // clones of unresolved backtraces resolve on their own
+    let bt = Backtrace::capture().unwrap();
+    let cloned = bt.clone();
+    assert_eq!(cloned.to_string(), bt.to_string());
+
+    let symbol = sym("app::handler").with_column(3);
+    assert_eq!(symbol.clone(), symbol);
+    let name = symbol.name().unwrap();
+    assert_eq!(name.clone().to_string(), name.to_string());
+}
+
+#[test]
+fn test_len() {
+    let bt = Backtrace::capture().unwrap();
+    assert!(!bt.is_empty());
+    assert_eq!(bt.iter_frames().len(), bt.len());
+    assert_eq!(bt.iter_frames().size_hint(), (bt.len(), Some(bt.len())));
+
+    let mut iter = bt.iter_frames();
+    iter.next();
+    assert_eq!(iter.len(), bt.len() - 1);
+    assert_eq!(iter.size_hint(), (bt.len() - 1, Some(bt.len() - 1)));
+
+    let empty = Backtrace::empty();
+    assert_eq!(empty.len(), 0);
+    assert!(empty.is_empty());
+    assert_eq!(empty.iter_frames().size_hint(), (0, Some(0)));
+}
+
+#[test]
+fn test_capture_with_limit() {
+    let full = Backtrace::capture().unwrap();
+    assert!(!full.is_truncated());
+
+    let bt = Backtrace::capture_with_limit(2).unwrap();
+    assert_eq!(bt.len(), 2);
+    assert!(bt.is_truncated());
+    assert!(bt.to_string().ends_with("\n  ... (truncated)"));
+
+    let bt = Backtrace::capture_with_limit(usize::MAX).unwrap();
+    assert!(!bt.is_truncated());
+    assert!(!bt.to_string().contains("(truncated)"));
+}
+
+#[inline(never)]
+fn light_call_site() -> Backtrace {
+    Backtrace::capture_light().unwrap()
+}
+
+#[test]
+fn test_capture_light() {
+    let bt = light_call_site();
+    assert!(bt.len() <= 3);
+    assert!(bt.is_truncated());
+    let trimmed = bt.trimmed();
+    assert!(!trimmed.is_empty());
+    let name = trimmed.frames()[0].symbol_name().unwrap();
+    assert!(name.ends_with("light_call_site"), "{}", name);
+}
+
+/// The output without the header, which shows the thread of the capture.
+fn frames_display(output: String) -> String {
+    output.split_once('\n').map_or("", |x| x.1).to_string()
+}
+
+#[test]
+fn test_string_repr_roundtrip() {
+    let bt = Backtrace::capture().unwrap();
+    let parsed = Backtrace::from_string_repr(&bt.to_string_repr()).unwrap();
+    assert_eq!(parsed.len(), bt.len());
+    assert_eq!(parsed, bt);
+    assert!(!frames_display(bt.to_string()).is_empty());
+    assert_eq!(
+        frames_display(parsed.to_string()),
+        frames_display(bt.to_string())
+    );
+    assert_eq!(
+        frames_display(format!("{:#}", parsed)),
+        frames_display(format!("{:#}", bt))
+    );
+    assert_eq!(parsed.to_string_repr(), bt.to_string_repr());
+
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x10 as *mut _,
+            AddrHint::Precise,
+            vec![
+                Symbol::new(Some(b"a|b\\c\xff".to_vec()), Some(0x8), None, Some(1)),
+                Symbol::new(None, None, Some(PathBuf::from("/src/ü.rs")), None).with_column(4),
+            ],
+        ),
+        Frame::new_resolved(0x20 as *mut _, AddrHint::Unknown, vec![]),
+    ]);
+    let repr = bt.to_string_repr();
+    assert_eq!(
+        repr,
+        "0x10|precise|a\\|b\\\\c\\xff|0x8||1||||/src/ü.rs||4\n0x20|unknown\n"
+    );
+    let parsed = Backtrace::from_string_repr(&repr).unwrap();
+    assert_eq!(parsed.to_string_repr(), repr);
+    let symbols = parsed.frames()[0].symbols();
+    assert_eq!(symbols[0].name().unwrap().as_bytes(), b"a|b\\c\xff");
+    assert_eq!(symbols[0].addr(), Some(0x8 as *mut _));
+    assert_eq!(symbols[1].filename(), Some(Path::new("/src/ü.rs")));
+    assert_eq!(symbols[1].column(), Some(4));
+    assert!(parsed.frames()[1].symbols().is_empty());
+}
+
+#[test]
+fn test_string_repr_errors() {
+    let err = |s: &str| {
+        let err = Backtrace::from_string_repr(s).unwrap_err();
+        (err.line(), err.to_string())
+    };
+    assert_eq!(
+        err("0x10|precise\nnope|return"),
+        (2, "invalid instruction pointer (line 2)".to_string())
+    );
+    assert_eq!(
+        err("0x10|sideways"),
+        (1, "invalid address hint (line 1)".to_string())
+    );
+    assert_eq!(
+        err("0x10|precise|name"),
+        (1, "unexpected number of fields (line 1)".to_string())
+    );
+    assert_eq!(
+        err("0x10|precise|name|||x|"),
+        (1, "invalid line number (line 1)".to_string())
+    );
+    assert_eq!(
+        err("0x10|precise|\\q||||"),
+        (1, "invalid escape sequence (line 1)".to_string())
+    );
+    assert_eq!(
+        err("...\n0x10|precise"),
+        (
+            2,
+            "unexpected data after truncation marker (line 2)".to_string()
+        )
+    );
+    assert!(Backtrace::from_string_repr("0x10|precise\n...\n")
+        .unwrap()
+        .is_truncated());
+}
+
+fn call_ip(ip: usize, hint: AddrHint) -> usize {
+    Frame::new(ip as *mut _, hint).call_ip() as usize
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn test_call_ip_x86_64() {
+    assert_eq!(call_ip(0x1000, AddrHint::Precise), 0x1000);
+    assert_eq!(call_ip(0x1000, AddrHint::Return), 0xfff);
+    assert_eq!(call_ip(0, AddrHint::Return), 0);
+}
+
+#[test]
+#[cfg(target_arch = "aarch64")]
+fn test_call_ip_aarch64() {
+    assert_eq!(call_ip(0x1000, AddrHint::Precise), 0x1000);
+    assert_eq!(call_ip(0x1000, AddrHint::Return), 0xffc);
+    assert_eq!(call_ip(2, AddrHint::Return), 2);
+    assert_eq!(call_ip(0, AddrHint::Return), 0);
+}
+
+#[test]
+fn test_symbol_column() {
+    let symbol = Symbol::new(
+        Some(b"app::handler".to_vec()),
+        None,
+        Some(PathBuf::from("/src/app/handler.rs")),
+        Some(42),
+    );
+    assert_eq!(symbol.column(), None);
+    assert!(format!("{:?}", symbol).contains("column: None"));
+    assert_eq!(symbol.to_string(), "app::handler (handler.rs:42)");
+
+    let symbol = symbol.with_column(17);
+    assert_eq!(symbol.column(), Some(17));
+    assert!(format!("{:?}", symbol).contains("column: Some(17)"));
+    assert_eq!(symbol.to_string(), "app::handler (handler.rs:42:17)");
+}
+
+#[test]
+fn test_resolved_column() {
+    let bt = Backtrace::capture().unwrap();
+    assert!(bt.iter_symbols().any(|(_, _, sym)| sym.column().is_some()));
+}
+
+const ENABLED: &[&str] = &[
+    "1", "true", "yes", "on", "full", "short", "TRUE", "Yes", "FULL",
+];
+const DISABLED: &[&str] = &["0", "false", "no", "off", "", "OFF", "False"];
+
+fn captures(vars: &[(&str, &str)], purpose: CapturePurpose) -> bool {
+    with_env(vars, || {
+        let rv = Backtrace::conditional_capture(purpose).is_some();
+        assert_eq!(Backtrace::is_enabled(purpose), rv);
+        rv
+    })
+}
+
+#[test]
+fn test_env_spellings() {
+    for &value in ENABLED {
+        for &var in &["RUST_ERROR_BACKTRACE", "RUST_BACKTRACE"] {
+            assert!(
+                captures(&[(var, value)], CapturePurpose::Error),
+                "{}={}",
+                var,
+                value
+            );
+        }
+        for &var in &["RUST_PANIC_BACKTRACE", "RUST_BACKTRACE"] {
+            assert!(
+                captures(&[(var, value)], CapturePurpose::Panic),
+                "{}={}",
+                var,
+                value
+            );
+        }
+    }
+    for &value in DISABLED {
+        for &var in &["RUST_ERROR_BACKTRACE", "RUST_BACKTRACE"] {
+            assert!(
+                !captures(&[(var, value)], CapturePurpose::Error),
+                "{}={}",
+                var,
+                value
+            );
+        }
+        for &var in &["RUST_PANIC_BACKTRACE", "RUST_BACKTRACE"] {
+            assert!(
+                !captures(&[(var, value)], CapturePurpose::Panic),
+                "{}={}",
+                var,
+                value
+            );
+        }
+    }
+    assert!(!captures(&[], CapturePurpose::Error));
+}
+
+#[test]
+fn test_env_precedence() {
+    let error = CapturePurpose::Error;
+    assert!(!captures(
+        &[("RUST_ERROR_BACKTRACE", "0"), ("RUST_BACKTRACE", "1")],
+        error
+    ));
+    assert!(captures(
+        &[("RUST_ERROR_BACKTRACE", "yes"), ("RUST_BACKTRACE", "0")],
+        error
+    ));
+    // unknown values fall through to the next variable
+    assert!(captures(
+        &[("RUST_ERROR_BACKTRACE", "maybe"), ("RUST_BACKTRACE", "1")],
+        error
+    ));
+    // the panic variable does not apply to errors
+    assert!(!captures(&[("RUST_PANIC_BACKTRACE", "1")], error));
+
+    let full = with_env(&[("RUST_BACKTRACE", "full")], || {
+        Backtrace::conditional_capture(error).unwrap()
+    });
+    assert!(full.was_full_requested());
+    let short = with_env(&[("RUST_BACKTRACE", "short")], || {
+        Backtrace::conditional_capture(error).unwrap()
+    });
+    assert!(!short.was_full_requested());
+}
+
+#[test]
+fn test_lib_backtrace_precedence() {
+    let error = CapturePurpose::Error;
+    assert!(captures(&[("RUST_LIB_BACKTRACE", "1")], error));
+    assert!(!captures(&[("RUST_LIB_BACKTRACE", "0")], error));
+    // `RUST_LIB_BACKTRACE` ranks above `RUST_BACKTRACE`
+    assert!(!captures(
+        &[("RUST_LIB_BACKTRACE", "0"), ("RUST_BACKTRACE", "1")],
+        error
+    ));
+    assert!(captures(
+        &[("RUST_LIB_BACKTRACE", "1"), ("RUST_BACKTRACE", "0")],
+        error
+    ));
+    // and below `RUST_ERROR_BACKTRACE`
+    assert!(!captures(
+        &[("RUST_ERROR_BACKTRACE", "0"), ("RUST_LIB_BACKTRACE", "1")],
+        error
+    ));
+    assert!(captures(
+        &[("RUST_ERROR_BACKTRACE", "1"), ("RUST_LIB_BACKTRACE", "0")],
+        error
+    ));
+    assert!(!captures(
+        &[
+            ("RUST_ERROR_BACKTRACE", "0"),
+            ("RUST_LIB_BACKTRACE", "1"),
+            ("RUST_BACKTRACE", "1"),
+        ],
+        error
+    ));
+    assert!(captures(
+        &[
+            ("RUST_ERROR_BACKTRACE", "maybe"),
+            ("RUST_LIB_BACKTRACE", "1"),
+            ("RUST_BACKTRACE", "0"),
+        ],
+        error
+    ));
+    let full = with_env(
+        &[("RUST_LIB_BACKTRACE", "full"), ("RUST_BACKTRACE", "1")],
+        || Backtrace::conditional_capture(error).unwrap(),
+    );
+    assert!(full.was_full_requested());
+
+    // panics do not consult `RUST_LIB_BACKTRACE`
+    let panic = CapturePurpose::Panic;
+    assert!(!captures(&[("RUST_LIB_BACKTRACE", "1")], panic));
+    assert!(captures(
+        &[("RUST_LIB_BACKTRACE", "0"), ("RUST_BACKTRACE", "1")],
+        panic
+    ));
+}
+
+#[test]
+fn test_env_purpose() {
+    let purpose = CapturePurpose::Env("DEFEAT_TEST_DB_BACKTRACE");
+    assert!(captures(&[("DEFEAT_TEST_DB_BACKTRACE", "1")], purpose));
+    assert!(!captures(&[("DEFEAT_TEST_DB_BACKTRACE", "0")], purpose));
+    // falls back to `RUST_BACKTRACE` if not set
+    assert!(!captures(&[], purpose));
+    assert!(captures(&[("RUST_BACKTRACE", "1")], purpose));
+    assert!(!captures(
+        &[("DEFEAT_TEST_DB_BACKTRACE", "off"), ("RUST_BACKTRACE", "1")],
+        purpose
+    ));
+    // the error variable does not apply
+    assert!(!captures(&[("RUST_ERROR_BACKTRACE", "1")], purpose));
+
+    let bt = with_env(&[("DEFEAT_TEST_DB_BACKTRACE", "1")], || {
+        Backtrace::conditional_capture(purpose).unwrap()
+    });
+    assert_eq!(bt.purpose(), Some(purpose));
+}
+
+#[test]
+fn test_resolve() {
+    let bt = Backtrace::capture().unwrap();
+    assert!(bt
+        .iter_frames()
+        .all(|x| x.resolution_state() == ResolutionState::Unresolved));
+    bt.resolve();
+    assert!(bt
+        .iter_frames()
+        .all(|x| x.resolution_state() != ResolutionState::Unresolved));
+    assert!(bt
+        .iter_frames()
+        .any(|x| x.resolution_state() == ResolutionState::Resolved));
+    // resolved symbols are kept and not looked up again
+    let frame = &bt.frames()[0];
+    assert_eq!(
+        frame.resolve_with(|_| panic!("resolved again")),
+        frame.symbols()
+    );
+}
+
+fn names(bt: &Backtrace) -> Vec<String> {
+    bt.iter_symbols()
+        .map(|(_, _, sym)| sym.name().map_or("?".into(), |x| x.to_string()))
+        .collect()
+}
+
+fn synthetic(names: &[&str]) -> Backtrace {
+    Backtrace::from_frames(
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                Frame::new_resolved(
+                    (0x1000 + idx * 0x10) as *mut _,
+                    AddrHint::Return,
+                    vec![sym(name)],
+                )
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn test_filter_frames() {
+    let bt = synthetic(&[
+        "app::handler",
+        "tokio::runtime::poll",
+        "core::future::poll",
+        "app::main",
+    ]);
+    let filtered = bt.filter_frames(|frame| {
+        frame
+            .symbol_name()
+            .is_some_and(|x| !x.starts_with("tokio::") && !x.starts_with("core::future::"))
+    });
+    assert_eq!(names(&filtered), vec!["app::handler", "app::main"]);
+    assert_eq!(filtered.frames()[1].ip() as usize, 0x1030);
+}
+
+#[test]
+fn test_trimmed_with_options() {
+    let bt = synthetic(&[
+        "app::errors::make_error",
+        "app::handler",
+        "app::run",
+        "test::run_test::inner",
+        "std::panicking::try",
+    ]);
+    assert_eq!(names(&bt.clone().trimmed()).len(), 5);
+
+    let options = TrimOptions::new().boundary_prefix("test::run_test");
+    assert_eq!(
+        names(&bt.clone().trimmed_with(&options)),
+        vec!["app::errors::make_error", "app::handler", "app::run"]
+    );
+
+    let options = options.internal_prefix("app::errors::");
+    assert_eq!(
+        names(&bt.clone().trimmed_with(&options)),
+        vec!["app::handler", "app::run"]
+    );
+
+    // `std::rt::lang_start` is always a boundary
+    let bt = synthetic(&["app::main", "std::rt::lang_start::{{closure}}", "main"]);
+    assert_eq!(
+        names(&bt.trimmed_with(&TrimOptions::new())),
+        vec!["app::main"]
+    );
+}
+
+#[test]
+fn test_inlined() {
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![sym("app::inlined"), sym("app::outer")],
+    );
+    assert_eq!(frame.inline_count(), 1);
+    assert_eq!(
+        frame.to_string(),
+        "  in app::inlined [inlined]\n  in app::outer"
+    );
+
+    let frame = Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![sym("app::outer")]);
+    assert_eq!(frame.inline_count(), 0);
+    assert!(!frame.to_string().contains("[inlined]"));
+}
+
+#[test]
+fn test_iter_symbols() {
+    let bt = Backtrace::capture().unwrap();
+    let total: usize = bt.iter_frames().map(|x| x.symbols().len()).sum();
+    assert_eq!(bt.iter_symbols().count(), total);
+
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Precise,
+            vec![sym("app::inlined"), sym("app::outer")],
+        ),
+        Frame::new_resolved(0x2000 as *mut _, AddrHint::Return, vec![]),
+        Frame::new_resolved(0x3000 as *mut _, AddrHint::Return, vec![sym("app::main")]),
+    ]);
+    let symbols: Vec<_> = bt
+        .iter_symbols()
+        .map(|(ip, hint, sym)| (ip as usize, hint, sym.name().unwrap().to_string()))
+        .collect();
+    assert_eq!(
+        symbols,
+        vec![
+            (0x1000, AddrHint::Precise, "app::inlined".to_string()),
+            (0x1000, AddrHint::Precise, "app::outer".to_string()),
+            (0x3000, AddrHint::Return, "app::main".to_string()),
+        ]
+    );
+}
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn located(ip: usize, name: &str, lineno: u32) -> Frame {
+    Frame::new_resolved(
+        ip as *mut _,
+        AddrHint::Return,
+        vec![Symbol::new(
+            Some(name.as_bytes().to_vec()),
+            Some(ip - 0x10),
+            Some(PathBuf::from("/src/app.rs")),
+            Some(lineno),
+        )],
+    )
+}
+
+#[test]
+fn test_equality_ignores_addresses() {
+    let a = Backtrace::from_frames(vec![
+        located(0x1010, "app::a", 1),
+        located(0x2010, "app::b", 2),
+    ]);
+    let b = Backtrace::from_frames(vec![
+        located(0x7010, "app::a", 1),
+        located(0x9010, "app::b", 2),
+    ]);
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+    assert_eq!(a.fingerprint(), b.fingerprint());
+
+    let c = Backtrace::from_frames(vec![
+        located(0x1010, "app::a", 1),
+        located(0x2010, "app::b", 3),
+    ]);
+    assert_ne!(a, c);
+    assert_ne!(a.fingerprint(), c.fingerprint());
+
+    let d = Backtrace::from_frames(vec![
+        located(0x1010, "app::a", 1),
+        located(0x2010, "app::c", 2),
+    ]);
+    assert_ne!(a, d);
+    assert_ne!(a.fingerprint(), d.fingerprint());
+}
+
+#[test]
+fn test_compact_display() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Return,
+            vec![Symbol::new(
+                Some(b"_ZN3app7handler17h0123456789abcdefE".to_vec()),
+                None,
+                Some(PathBuf::from("/src/app.rs")),
+                Some(4),
+            )],
+        ),
+        Frame::new_resolved(0x2000 as *mut _, AddrHint::Return, vec![sym("app::main")]),
+    ]);
+    assert_eq!(bt.compact_display().to_string(), "app::handler<-app::main");
+
+    let bt = Backtrace::capture().unwrap();
+    let compact = bt.compact_display().to_string();
+    assert!(!compact.contains('\n'));
+    assert!(compact.contains("<-"));
+    assert!(compact.contains("test_compact_display"));
+}
+
+#[test]
+fn test_full_is_not_trimmed() {
+    let bt = with_env(&[("RUST_BACKTRACE", "1")], || {
+        Backtrace::conditional_capture(CapturePurpose::Error).unwrap()
+    });
+    assert!(!bt.was_full_requested());
+    let len = bt.len();
+    let trimmed = bt.trimmed();
+    assert!(trimmed.len() < len);
+    assert!(!trimmed.to_string().contains("defeat::"));
+
+    let bt = with_env(&[("RUST_BACKTRACE", "full")], || {
+        Backtrace::conditional_capture(CapturePurpose::Error).unwrap()
+    });
+    assert!(bt.was_full_requested());
+    let len = bt.len();
+    let trimmed = bt.trimmed();
+    assert_eq!(trimmed.len(), len);
+    assert!(trimmed.to_string().contains("defeat::"));
+}
+
+fn in_file(name: &str, path: &str) -> Symbol {
+    Symbol::new(
+        Some(name.as_bytes().to_vec()),
+        None,
+        Some(PathBuf::from(path)),
+        Some(1),
+    )
+}
+
+#[test]
+fn test_relative_path() {
+    let base = Path::new("/home/runner/project");
+    let symbol = in_file("app::main", "/home/runner/project/src/main.rs");
+    assert_eq!(
+        symbol.relative_path(base),
+        Some(PathBuf::from("src/main.rs"))
+    );
+    let symbol = in_file("std::rt::lang_start", "/rustc/library/std/src/rt.rs");
+    assert_eq!(symbol.relative_path(base), None);
+    let symbol = sym("app::main");
+    assert_eq!(symbol.relative_path(base), None);
+
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Return,
+            vec![in_file("app::main", "/home/runner/project/src/main.rs")],
+        ),
+        Frame::new_resolved(
+            0x2000 as *mut _,
+            AddrHint::Return,
+            vec![in_file(
+                "std::rt::lang_start",
+                "/rustc/library/std/src/rt.rs",
+            )],
+        ),
+    ])
+    .strip_path_prefix(base);
+    let files: Vec<_> = bt
+        .iter_symbols()
+        .map(|(_, _, sym)| sym.filename().unwrap().to_path_buf())
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("/rustc/library/std/src/rt.rs")
+        ]
+    );
+}
+
+#[test]
+fn test_symbol_name() {
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![
+            sym("_ZN3app6helper17h0123456789abcdefE"),
+            sym("_ZN3app4main17h0123456789abcdefE"),
+        ],
+    );
+    assert_eq!(frame.symbol_name().as_deref(), Some("app::helper"));
+
+    let frame = Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![sym("memcpy")]);
+    assert_eq!(frame.symbol_name().as_deref(), Some("memcpy"));
+
+    let frame = Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![]);
+    assert_eq!(frame.symbol_name(), None);
+}
+
+#[test]
+fn test_trimmed_unresolved() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![sym("app::main")]),
+        Frame::new_resolved(0x2000 as *mut _, AddrHint::Return, vec![]),
+        Frame::new_resolved(
+            0x3000 as *mut _,
+            AddrHint::Return,
+            vec![Symbol::new(None, Some(0x2ff0), None, None)],
+        ),
+        Frame::new_resolved(0x4000 as *mut _, AddrHint::Return, vec![sym("app::run")]),
+    ]);
+    assert_eq!(names(&bt.clone().trimmed()), vec!["app::main", "app::run"]);
+
+    let kept = bt.trimmed_with(&TrimOptions::new().keep_unresolved(true));
+    assert_eq!(kept.len(), 4);
+    assert_eq!(kept.frames()[1].ip() as usize, 0x2000);
+    assert!(kept.frames()[1].symbols().is_empty());
+}
+
+#[test]
+fn test_trimmed_keeps_locations() {
+    let loc = std::panic::Location::caller();
+    let bt = Backtrace::from_location(loc).trimmed();
+    assert_eq!(bt.len(), 1);
+    let symbol = &bt.frames()[0].symbols()[0];
+    assert_eq!(symbol.lineno(), Some(loc.line()));
+
+    let output = Backtrace::from_location(loc).short_display(3).to_string();
+    assert!(output.contains(&format!(":{}", loc.line())), "{}", output);
+}
+
+#[test]
+fn test_resolve_within() {
+    let bt = Backtrace::capture().unwrap();
+    assert!(!bt.resolve_within(Duration::from_secs(0)));
+    assert!(bt
+        .iter_frames()
+        .all(|x| x.resolution_state() == ResolutionState::Unresolved));
+
+    assert!(bt.resolve_within(Duration::from_secs(60)));
+    assert!(bt
+        .iter_frames()
+        .all(|x| x.resolution_state() != ResolutionState::Unresolved));
+    // nothing is left to resolve so even a zero budget completes
+    assert!(bt.resolve_within(Duration::from_secs(0)));
+}
+
+#[test]
+fn test_unknown_addr_hint() {
+    assert_eq!(
+        call_ip(0x1000, AddrHint::Unknown),
+        call_ip(0x1000, AddrHint::Return)
+    );
+    let bt = Backtrace::capture().unwrap();
+    for frame in bt
+        .iter_frames()
+        .filter(|x| x.addr_hint() == AddrHint::Return)
+    {
+        let unknown = Frame::new(frame.ip(), AddrHint::Unknown);
+        let ret = Frame::new(frame.ip(), AddrHint::Return);
+        assert_eq!(unknown.call_ip(), ret.call_ip());
+        assert_eq!(unknown.symbols(), ret.symbols());
+    }
+}
+
+#[inline(never)]
+fn wrapper_inner() -> (Backtrace, Backtrace) {
+    (
+        Backtrace::capture().unwrap(),
+        Backtrace::capture_skipping(2).unwrap(),
+    )
+}
+
+#[inline(never)]
+fn wrapper_outer() -> (Backtrace, Backtrace) {
+    wrapper_inner()
+}
+
+#[test]
+fn test_capture_skipping() {
+    let (full, skipped) = wrapper_outer();
+    let full = full.trimmed();
+    let skipped = skipped.trimmed();
+    assert_eq!(skipped.len(), full.len() - 2);
+    assert!(names(&full)[0].contains("wrapper_inner"));
+    assert!(names(&full)[1].contains("wrapper_outer"));
+    assert!(!names(&skipped).iter().any(|x| x.contains("wrapper_")));
+    assert!(names(&skipped)[0].contains("test_capture_skipping"));
+}
+
+#[inline(never)]
+fn range_inner(skip: usize, take: usize) -> Backtrace {
+    Backtrace::capture_range(skip, take).unwrap()
+}
+
+#[inline(never)]
+fn range_outer(skip: usize, take: usize) -> Backtrace {
+    range_inner(skip, take)
+}
+
+#[test]
+fn test_capture_range() {
+    let bt = range_outer(2, 3);
+    assert_eq!(bt.len(), 3);
+    assert!(bt.is_truncated());
+    let kept = names(&bt);
+    assert!(!kept.iter().any(|x| x.contains("range_")), "{:?}", kept);
+    assert!(kept[0].contains("test_capture_range"), "{:?}", kept);
+
+    // without skipping the wrappers are the first frames
+    let kept = names(&range_outer(0, 2));
+    assert!(kept[0].contains("range_inner"), "{:?}", kept);
+    assert!(kept[1].contains("range_outer"), "{:?}", kept);
+
+    let full = range_outer(0, usize::MAX);
+    assert!(!full.is_truncated());
+    assert_eq!(range_outer(2, usize::MAX).len(), full.len() - 2);
+}
+
+#[test]
+fn test_trimmed_v0_symbols() {
+    let method = "_RNvMs_Cs4Cv8Wi1oAIB_6defeatNtB4_9Backtrace7capture";
+    assert_eq!(
+        SymbolName::new(method.as_bytes()).demangled(),
+        Some("<defeat::Backtrace>::capture")
+    );
+    let bt = synthetic(&[
+        "_RNvMs_Cs4Cv8Wi1oAIB_6defeatNtB4_9Backtrace7capture",
+        "_RNvNtCs15kBYyAo9fc_6defeat9backtrace18capture_backtrace",
+        "_RNvCs15kBYyAo9fc_3app4main",
+        "_RNvNtCs1234_3std2rt10lang_start",
+        "main",
+    ]);
+    assert_eq!(names(&bt.trimmed()), vec!["app::main"]);
+}
+
+#[test]
+fn test_capture_for() {
+    for &purpose in &[
+        CapturePurpose::Panic,
+        CapturePurpose::Error,
+        CapturePurpose::Env("DEFEAT_TEST_DB_BACKTRACE"),
+    ] {
+        let bt = with_env(&[("RUST_BACKTRACE", "0")], || {
+            Backtrace::capture_for(purpose).unwrap()
+        });
+        assert!(!bt.is_empty());
+        assert_eq!(bt.purpose(), Some(purpose));
+        assert_eq!(bt.clone().purpose(), Some(purpose));
+        assert_eq!(bt.trimmed().purpose(), Some(purpose));
+    }
+    assert_eq!(Backtrace::capture().unwrap().purpose(), None);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_module() {
+    let bt = Backtrace::capture().unwrap();
+    let frame = &bt.frames()[0];
+    let base = frame.module_base().unwrap();
+    assert!(!base.is_null());
+    assert!(base as usize <= frame.ip() as usize);
+    let exe = std::env::current_exe().unwrap();
+    assert_eq!(
+        frame.module_name().map(|x| x.file_name()),
+        Some(exe.file_name())
+    );
+    // frames of the same module share the base
+    for other in bt.iter_frames() {
+        if other.module_name() == frame.module_name() {
+            assert_eq!(other.module_base(), Some(base));
+        }
+    }
+
+    let frame = Frame::new(0x10 as *mut _, AddrHint::Precise);
+    assert_eq!(frame.module_base(), None);
+    assert_eq!(frame.module_name(), None);
+}
+
+#[test]
+fn test_write_to() {
+    let bt = Backtrace::capture().unwrap();
+    let mut buf = Vec::new();
+    bt.write_to(&mut buf, false).unwrap();
+    assert_eq!(buf, bt.to_string().into_bytes());
+
+    let mut buf = Vec::new();
+    bt.write_to(&mut buf, true).unwrap();
+    assert_eq!(buf, format!("{:#}", bt).into_bytes());
+
+    let bt = Backtrace::capture_with_limit(2).unwrap();
+    let mut buf = Vec::new();
+    bt.write_to(&mut buf, false).unwrap();
+    assert_eq!(buf, bt.to_string().into_bytes());
+    assert!(String::from_utf8(buf).unwrap().ends_with("... (truncated)"));
+}
+
+#[cfg(feature = "color")]
+fn strip_ansi(s: &str) -> String {
+    let mut rv = String::new();
+    let mut in_escape = false;
+    for c in s.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if !in_escape => rv.push(c),
+            _ => {}
+        }
+    }
+    rv
+}
+
+#[test]
+#[cfg(feature = "color")]
+fn test_display_colored() {
+    let bt = Backtrace::from_frames(vec![Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![in_file("app::main", "/src/app/main.rs")],
+    )]);
+    assert!(!bt.to_string().contains('\x1b'));
+
+    let (colored, alternate) = with_env(&[("NO_COLOR", ""), ("CLICOLOR", "1")], || {
+        (
+            bt.display_colored().to_string(),
+            format!("{:#}", bt.display_colored()),
+        )
+    });
+    assert!(colored.contains("\x1b["));
+    assert_eq!(strip_ansi(&colored), bt.to_string());
+    assert_eq!(strip_ansi(&alternate), format!("{:#}", bt));
+
+    for &vars in &[&[("NO_COLOR", "1")][..], &[("CLICOLOR", "0")][..]] {
+        let plain = with_env(vars, || bt.display_colored().to_string());
+        assert_eq!(plain, bt.to_string());
+    }
+}
+
+#[test]
+fn test_short_display() {
+    let bt = synthetic(&["app::a", "app::b", "app::c", "app::d", "app::e"]);
+    let output = bt.short_display(3).to_string();
+    let frames: Vec<_> = output.lines().filter(|x| x.starts_with("  in ")).collect();
+    assert_eq!(frames, vec!["  in app::a", "  in app::b", "  in app::c"]);
+    assert!(output.ends_with("\n  ... (2 more frames)"), "{}", output);
+
+    let output = bt.short_display(5).to_string();
+    assert_eq!(output.lines().filter(|x| x.starts_with("  in ")).count(), 5);
+    assert!(!output.contains("more frames"));
+    assert_eq!(output, bt.to_string());
+}
+
+#[test]
+fn test_symbol_new() {
+    let symbol = Symbol::new(
+        Some(b"_ZN3app7handler17h0123456789abcdefE".to_vec()),
+        Some(0x1200),
+        Some(PathBuf::from("/src/app/handler.rs")),
+        Some(42),
+    );
+    assert_eq!(
+        symbol.name().unwrap().as_str(),
+        Some("_ZN3app7handler17h0123456789abcdefE")
+    );
+    assert_eq!(symbol.addr(), Some(0x1200 as *mut _));
+    assert_eq!(symbol.filename(), Some(Path::new("/src/app/handler.rs")));
+    assert_eq!(symbol.lineno(), Some(42));
+    assert_eq!(symbol.to_string(), "app::handler (handler.rs:42)");
+
+    let symbol = Symbol::new(None, None, None, None);
+    assert!(symbol.name().is_none());
+    assert!(symbol.addr().is_none());
+    assert!(symbol.filename().is_none());
+    assert!(symbol.lineno().is_none());
+    assert_eq!(symbol.to_string(), "?");
+}
+
+#[test]
+fn test_new_resolved() {
+    let bt = Backtrace::capture().unwrap();
+    let ip = bt.frames()[0].ip();
+    let frame = Frame::new_resolved(ip, AddrHint::Return, vec![sym("offline::symbol")]);
+    assert_eq!(frame.resolution_state(), ResolutionState::Resolved);
+    assert_eq!(frame.symbols(), &[sym("offline::symbol")][..]);
+    assert_eq!(
+        frame.resolve_with(|_| panic!("resolved again")),
+        &[sym("offline::symbol")][..]
+    );
+
+    let frame = Frame::new_resolved(ip, AddrHint::Return, vec![]);
+    assert_eq!(frame.resolution_state(), ResolutionState::ResolvedEmpty);
+    assert!(frame.symbols().is_empty());
+}
+
+#[track_caller]
+fn error_site() -> Backtrace {
+    Backtrace::from_location(std::panic::Location::caller())
+}
+
+#[test]
+fn test_from_location() {
+    let line = line!() + 1;
+    let bt = error_site();
+    assert_eq!(bt.len(), 1);
+    let frame = &bt.frames()[0];
+    assert!(frame.ip().is_null());
+    let symbol = &frame.symbols()[0];
+    assert!(symbol.name().is_none());
+    assert_eq!(symbol.filename(), Some(Path::new(file!())));
+    assert_eq!(symbol.lineno(), Some(line));
+    let output = bt.to_string();
+    assert!(
+        output.contains(&format!("backtraces.rs:{}:", line)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_split_inlined() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Return,
+            vec![sym("app::inlined"), sym("app::outer")],
+        ),
+        Frame::new_resolved(0x2000 as *mut _, AddrHint::Precise, vec![sym("app::main")]),
+    ]);
+    let split = bt.clone().split_inlined();
+    assert_eq!(split.len(), 3);
+    assert_eq!(
+        names(&split),
+        vec!["app::inlined", "app::outer", "app::main"]
+    );
+    let ips: Vec<_> = split.iter_frames().map(|x| x.ip() as usize).collect();
+    assert_eq!(ips, vec![0x1000, 0x1000, 0x2000]);
+    assert!(split.iter_frames().all(|x| x.symbols().len() == 1));
+    assert_eq!(split.frames()[1].addr_hint(), AddrHint::Return);
+    assert_eq!(split.fingerprint(), bt.split_inlined().fingerprint());
+}
+
+#[test]
+fn test_same_location() {
+    let ret = Frame::new(0x1001 as *mut _, AddrHint::Return);
+    let precise = Frame::new(ret.call_ip(), AddrHint::Precise);
+    assert_ne!(ret.ip(), precise.ip());
+    assert!(ret.same_location(&precise));
+    assert!(precise.same_location(&ret));
+    assert!(!ret.same_location(&Frame::new(0x2001 as *mut _, AddrHint::Return)));
+
+    let at_line = |ip: usize, hint: AddrHint, lineno: u32| {
+        let symbol = Symbol::new(None, None, Some(PathBuf::from("/src/app.rs")), Some(lineno));
+        Frame::new_resolved(ip as *mut _, hint, vec![symbol])
+    };
+    let a = at_line(0x1001, AddrHint::Return, 10);
+    let b = at_line(precise.ip() as usize, AddrHint::Precise, 10);
+    assert!(a.same_location(&b));
+    // resolved locations have to agree as well
+    let c = at_line(precise.ip() as usize, AddrHint::Precise, 11);
+    assert!(!a.same_location(&c));
+    // a shared file and line does not make up for different addresses
+    let d = at_line(0x5000, AddrHint::Precise, 10);
+    assert!(!a.same_location(&d));
+}
+
+#[inline(never)]
+fn known_function() -> usize {
+    42
+}
+
+#[test]
+fn test_capture_at() {
+    assert_eq!(known_function(), 42);
+    let ip = known_function as *const () as *mut _;
+    let bt = Backtrace::capture_at(ip, AddrHint::Precise);
+    assert_eq!(bt.len(), 1);
+    let frame = &bt.frames()[0];
+    assert_eq!(frame.ip(), ip);
+    assert_eq!(frame.resolution_state(), ResolutionState::Unresolved);
+    let name = frame.symbol_name().unwrap();
+    assert!(name.ends_with("known_function"), "{}", name);
+}
+
+#[test]
+fn test_alternate_alignment() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Precise,
+            vec![
+                in_file("app::inlined", "/src/app.rs"),
+                in_file("app::outer", "/src/app.rs"),
+            ],
+        ),
+        Frame::new_resolved(
+            0x2000 as *mut _,
+            AddrHint::Precise,
+            vec![in_file("app::a_much_longer_function_name", "/src/app.rs")],
+        ),
+    ]);
+    let output = format!("{:#}", bt);
+    let lines: Vec<_> = output.lines().skip(1).collect();
+    assert_eq!(lines.len(), 3);
+    // the ip takes a fixed column, continuation lines are indented to match
+    for line in &lines {
+        assert_eq!(line.find("in "), Some(2 + 14 + 1), "{}", output);
+    }
+    assert!(lines[0].starts_with(&format!("  {: >14} ", "0x1000")));
+    assert!(lines[1].starts_with(&format!("  {: >14} in ", "")));
+    // locations line up after the longest name
+    let columns: Vec<_> = lines.iter().map(|x| x.find(" (app.rs:")).collect();
+    assert!(columns[0].is_some());
+    assert!(columns.iter().all(|&x| x == columns[0]), "{}", output);
+}
+
+#[test]
+fn test_frames_slice() {
+    let bt = Backtrace::capture().unwrap();
+    let frames = bt.frames();
+    assert_eq!(frames.len(), bt.iter_frames().count());
+    for (idx, frame) in bt.iter_frames().enumerate() {
+        assert!(std::ptr::eq(&frames[idx], frame));
+    }
+    assert_eq!(frames.windows(2).count(), frames.len() - 1);
+    assert!(Backtrace::from_frames(vec![]).frames().is_empty());
+}
+
+#[test]
+fn test_iter_frames_rev() {
+    let bt = synthetic(&["app::a", "app::b", "app::c"]);
+    let forward: Vec<_> = bt.iter_frames().map(|x| x.ip()).collect();
+    let mut backward: Vec<_> = bt.iter_frames_rev().map(|x| x.ip()).collect();
+    backward.reverse();
+    assert_eq!(forward, backward);
+    let last: Vec<_> = bt.iter_frames().rev().take(1).map(|x| x.ip()).collect();
+    assert_eq!(last, vec![bt.frames()[2].ip()]);
+    // both ends can be consumed from the same iterator
+    let mut iter = bt.iter_frames();
+    assert_eq!(iter.next().unwrap().ip(), bt.frames()[0].ip());
+    assert_eq!(iter.next_back().unwrap().ip(), bt.frames()[2].ip());
+    assert_eq!(iter.next().unwrap().ip(), bt.frames()[1].ip());
+    assert!(iter.next_back().is_none());
+}
+
+#[test]
+fn test_capture_filtered() {
+    let bt = Backtrace::capture_filtered(|_| false).unwrap();
+    assert!(bt.is_empty());
+    assert_eq!(bt.len(), 0);
+
+    let mut seen = vec![];
+    let bt = Backtrace::capture_filtered(|ip| {
+        seen.push(ip);
+        seen.len() % 2 == 1
+    })
+    .unwrap();
+    assert!(!bt.is_empty());
+    let kept: Vec<_> = seen.iter().cloned().step_by(2).collect();
+    let ips: Vec<_> = bt.iter_frames().map(|x| x.ip()).collect();
+    assert_eq!(ips, kept);
+}
+
+#[test]
+fn test_is_enabled() {
+    let combinations: &[&[(&str, &str)]] = &[
+        &[],
+        &[("RUST_BACKTRACE", "1")],
+        &[("RUST_BACKTRACE", "0")],
+        &[("RUST_BACKTRACE", "full")],
+        &[("RUST_ERROR_BACKTRACE", "1"), ("RUST_BACKTRACE", "0")],
+        &[("RUST_PANIC_BACKTRACE", "0"), ("RUST_BACKTRACE", "1")],
+        &[("RUST_PANIC_BACKTRACE", "1")],
+        &[("DEFEAT_TEST_DB_BACKTRACE", "1")],
+        &[("DEFEAT_TEST_DB_BACKTRACE", "off"), ("RUST_BACKTRACE", "1")],
+    ];
+    let purposes = [
+        CapturePurpose::Error,
+        CapturePurpose::Panic,
+        CapturePurpose::Env("DEFEAT_TEST_DB_BACKTRACE"),
+    ];
+    let mut enabled = 0;
+    for vars in combinations {
+        for &purpose in &purposes {
+            // `captures` checks `is_enabled` against the actual capture
+            if captures(vars, purpose) {
+                enabled += 1;
+            }
+        }
+    }
+    assert!(enabled > 0 && enabled < combinations.len() * purposes.len());
+    assert!(!captures(&[], CapturePurpose::Error));
+    assert!(captures(
+        &[("RUST_PANIC_BACKTRACE", "1")],
+        CapturePurpose::Panic
+    ));
+}
+
+#[test]
+fn test_into_iterator() {
+    let bt = synthetic(&["app::a", "app::b", "app::c"]);
+    let mut ips = vec![];
+    for frame in &bt {
+        ips.push(frame.ip());
+    }
+    let expected: Vec<_> = bt.iter_frames().map(|x| x.ip()).collect();
+    assert_eq!(ips, expected);
+    assert_eq!((&bt).into_iter().count(), 3);
+}
+
+#[test]
+fn test_offset_in_symbol() {
+    let frame = located(0x1000, "app::handler", 1);
+    let offset = frame.call_ip() as usize - 0xff0;
+    assert_eq!(frame.offset_in_symbol(), Some(offset));
+
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Precise,
+        vec![Symbol::new(
+            Some(b"app::later".to_vec()),
+            Some(0x2000),
+            None,
+            None,
+        )],
+    );
+    assert_eq!(frame.offset_in_symbol(), None);
+    let frame = Frame::new_resolved(0x1000 as *mut _, AddrHint::Precise, vec![sym("app::x")]);
+    assert_eq!(frame.offset_in_symbol(), None);
+
+    let frame = Frame::new_resolved(
+        0x102a as *mut _,
+        AddrHint::Precise,
+        vec![Symbol::new(
+            Some(b"app::handler".to_vec()),
+            Some(0x1000),
+            None,
+            None,
+        )],
+    );
+    assert_eq!(frame.offset_in_symbol(), Some(0x2a));
+    assert!(format!("{:#}", frame).contains("app::handler+0x2a"));
+    assert!(!frame.to_string().contains("+0x2a"));
+}
+
+#[test]
+fn test_collapse_recursion() {
+    let mut frames = vec!["app::handle"];
+    frames.extend(std::iter::repeat_n("app::recurse", 37));
+    frames.push("app::main");
+    let bt = synthetic(&frames).collapse_recursion();
+    assert_eq!(names(&bt), vec!["app::handle", "app::recurse", "app::main"]);
+    let counts: Vec<_> = bt.iter_frames().map(|x| x.repeat_count()).collect();
+    assert_eq!(counts, vec![1, 37, 1]);
+    let output = bt.to_string();
+    assert!(output.contains("in app::recurse (×37)"), "{}", output);
+    assert!(!output.contains("app::main (×"));
+
+    // frames without names are never collapsed
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![]),
+        Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![]),
+    ]);
+    assert_eq!(bt.collapse_recursion().len(), 2);
+}
+
+#[test]
+fn test_display_precision() {
+    let bt = synthetic(&["app::a", "app::b", "app::c", "app::d", "app::e"]);
+    let output = format!("{:.2}", bt);
+    let frames: Vec<_> = output.lines().filter(|x| x.starts_with("  in ")).collect();
+    assert_eq!(frames, vec!["  in app::a", "  in app::b"]);
+    assert!(output.ends_with("\n  ... (3 more frames)"), "{}", output);
+
+    let output = format!("{:#.1}", bt);
+    assert_eq!(output.lines().filter(|x| x.contains(" in ")).count(), 1);
+    assert!(output.ends_with("(4 more frames)"));
+
+    assert_eq!(format!("{:.5}", bt), bt.to_string());
+    assert!(!bt.to_string().contains("more frames"));
+}
+
+#[test]
+fn test_capture_into() {
+    let mut buf = Vec::with_capacity(256);
+    assert!(Backtrace::capture_into(&mut buf));
+    assert!(!buf.is_empty());
+    let len = buf.len();
+    let capacity = buf.capacity();
+    let ptr = buf.as_ptr();
+
+    assert!(Backtrace::capture_into(&mut buf));
+    assert_eq!(buf.len(), len);
+    assert_eq!(buf.capacity(), capacity);
+    assert_eq!(buf.as_ptr(), ptr);
+
+    let bt = Backtrace::from_frames(buf);
+    assert!(bt.trimmed().to_string().contains("test_capture_into"));
+}
+
+#[test]
+fn test_resolution_state() {
+    // nothing is mapped at this address
+    let frame = Frame::new(0x10 as *mut _, AddrHint::Precise);
+    assert_eq!(frame.resolution_state(), ResolutionState::Unresolved);
+    assert!(frame.symbols().is_empty());
+    assert_eq!(frame.resolution_state(), ResolutionState::ResolvedEmpty);
+
+    let frame = Frame::new(known_function as *const () as *mut _, AddrHint::Precise);
+    assert_eq!(frame.resolution_state(), ResolutionState::Unresolved);
+    assert!(!frame.symbols().is_empty());
+    assert_eq!(frame.resolution_state(), ResolutionState::Resolved);
+}
+
+#[inline(never)]
+fn sampled_worker() -> Vec<usize> {
+    let bt = Backtrace::capture().unwrap();
+    bt.iter_frames().map(|x| x.ip() as usize).collect()
+}
+
+#[test]
+fn test_from_raw_ips() {
+    // ips sampled on another thread are symbolicated here
+    let ips = thread::spawn(sampled_worker).join().unwrap();
+    let ips: Vec<_> = ips.into_iter().map(|x| x as *mut _).collect();
+    let bt = Backtrace::from_raw_ips(&ips, AddrHint::Return);
+    assert_eq!(bt.len(), ips.len());
+    assert!(bt
+        .iter_frames()
+        .all(|x| x.resolution_state() == ResolutionState::Unresolved));
+    let frame_ips: Vec<_> = bt.iter_frames().map(|x| x.ip()).collect();
+    assert_eq!(frame_ips, ips);
+    assert!(bt.iter_frames().any(|x| x
+        .symbol_name()
+        .is_some_and(|x| x.ends_with("sampled_worker"))));
+}
+
+#[track_caller]
+fn capture_wrapper() -> Backtrace {
+    Backtrace::capture_with_caller(std::panic::Location::caller()).unwrap()
+}
+
+#[test]
+fn test_caller_frame() {
+    let line = line!() + 1;
+    let bt = capture_wrapper();
+    let caller = bt.caller().unwrap();
+    assert_eq!(caller.line(), line);
+    assert!(!bt.frames()[0].ip().is_null());
+
+    // the caller is shown at the top, also after trimming
+    let location = format!("captured at tests/backtraces.rs:{}:", line);
+    for output in &[bt.to_string(), bt.clone().trimmed().to_string()] {
+        let first = output.lines().next().unwrap();
+        assert!(first.contains(&location), "{}", output);
+    }
+    let trimmed = bt.clone().trimmed();
+    assert!(names(&trimmed)[0].contains("capture_wrapper"));
+
+    // the caller does not survive the string representation
+    let parsed = Backtrace::from_string_repr(&bt.to_string_repr()).unwrap();
+    assert!(parsed.caller().is_none());
+    assert!(!parsed.to_string().contains("captured at"));
+
+    // it does not change the frames
+    let line = line!() + 1;
+    let bt = Backtrace::capture().unwrap();
+    assert_eq!(bt.caller().unwrap().line(), line);
+    let limited = Backtrace::capture_with_limit(usize::MAX).unwrap();
+    assert!(limited.caller().is_none());
+    let bt = bt.trimmed();
+    let limited = limited.trimmed();
+    assert_eq!(bt.len(), limited.len());
+    assert_eq!(names(&bt)[0], names(&limited)[0]);
+}
+
+#[test]
+fn test_interned_paths() {
+    let bts: Vec<_> = (0..20).map(|_| Backtrace::capture().unwrap()).collect();
+    let mut paths = std::collections::HashSet::new();
+    let mut allocations = std::collections::HashSet::new();
+    for bt in &bts {
+        for (_, _, symbol) in bt.iter_symbols() {
+            if let Some(filename) = symbol.filename() {
+                paths.insert(filename.to_path_buf());
+                allocations.insert(filename.as_os_str().as_encoded_bytes().as_ptr());
+            }
+        }
+    }
+    assert!(!paths.is_empty());
+    // every distinct path is allocated once across all backtraces
+    assert_eq!(allocations.len(), paths.len());
+
+    let a = in_file("app::a", "/src/app/shared.rs");
+    let b = in_file("app::b", "/src/app/shared.rs");
+    assert!(std::ptr::eq(a.filename().unwrap(), b.filename().unwrap()));
+}
+
+#[test]
+fn test_truncate() {
+    let mut bt = Backtrace::capture().unwrap();
+    assert!(bt.len() > 5);
+    let top = bt.frames()[0].ip();
+    bt.truncate(5);
+    assert_eq!(bt.len(), 5);
+    assert!(bt.is_truncated());
+    // the most recent frames are kept
+    assert_eq!(bt.frames()[0].ip(), top);
+
+    // truncating to a larger length changes nothing
+    let mut bt = Backtrace::capture_with_limit(100).unwrap();
+    let len = bt.len();
+    bt.truncate(len);
+    assert_eq!(bt.len(), len);
+    assert!(!bt.is_truncated());
+
+    let mut empty = Backtrace::empty();
+    empty.truncate(5);
+    assert!(empty.is_empty());
+    assert!(!empty.is_truncated());
+}
+
+#[test]
+fn test_diff() {
+    let a = synthetic(&["app::read", "app::parse", "app::load", "app::main"]);
+    let b = synthetic(&["app::fetch", "app::load", "app::main"]);
+    let diff = a.diff(&b);
+    assert_eq!(diff.common_prefix(), 0);
+    assert_eq!(diff.common_suffix(), 2);
+    assert_eq!(diff.first_difference(), Some(0));
+    assert!(!diff.is_identical());
+
+    let c = synthetic(&["app::read", "app::cached", "app::load", "app::main"]);
+    let diff = a.diff(&c);
+    assert_eq!(diff.common_prefix(), 1);
+    assert_eq!(diff.common_suffix(), 2);
+    assert_eq!(diff.first_difference(), Some(1));
+
+    // a shorter backtrace that is a prefix of the other still differs
+    let d = synthetic(&["app::read", "app::parse"]);
+    assert_eq!(a.diff(&d).common_prefix(), 2);
+    assert_eq!(a.diff(&d).common_suffix(), 0);
+    assert_eq!(a.diff(&d).first_difference(), Some(2));
+
+    let diff = a.diff(&a.clone());
+    assert!(diff.is_identical());
+    assert_eq!(diff.first_difference(), None);
+    assert_eq!(diff.common_prefix(), 4);
+    assert_eq!(diff.common_suffix(), 0);
+}
+
+#[test]
+fn test_capture_sampled() {
+    for _ in 0..10 {
+        assert!(Backtrace::capture_sampled(1).is_some());
+    }
+    assert!(Backtrace::capture_sampled(0).is_none());
+
+    // counters are per thread, so a fresh thread samples deterministically
+    let captured = thread::spawn(|| {
+        (0..3000)
+            .filter_map(|_| Backtrace::capture_sampled(1000))
+            .collect::<Vec<_>>()
+    })
+    .join()
+    .unwrap();
+    assert_eq!(captured.len(), 3);
+    let names = names(&captured.into_iter().next().unwrap().trimmed());
+    assert!(names[0].contains("test_capture_sampled"), "{:?}", names);
+}
+
+#[test]
+fn test_location_string() {
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![in_file("app::handler", "/src/app/handler.rs").with_column(5)],
+    );
+    assert_eq!(
+        frame.location(),
+        Some((PathBuf::from("/src/app/handler.rs"), Some(1)))
+    );
+    assert_eq!(
+        frame.location_string().as_deref(),
+        Some("/src/app/handler.rs:1")
+    );
+
+    // inlined frames report the outermost symbol
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![
+            Symbol::new(None, None, Some(PathBuf::from("/src/util.rs")), Some(7)),
+            Symbol::new(None, None, Some(PathBuf::from("/src/main.rs")), Some(42)),
+        ],
+    );
+    assert_eq!(frame.location_string().as_deref(), Some("/src/main.rs:42"));
+
+    let frame = Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Return,
+        vec![Symbol::new(
+            None,
+            None,
+            Some(PathBuf::from("/src/app/handler.rs")),
+            None,
+        )],
+    );
+    assert_eq!(
+        frame.location_string().as_deref(),
+        Some("/src/app/handler.rs")
+    );
+    let frame = Frame::new_resolved(0x1000 as *mut _, AddrHint::Return, vec![sym("app::a")]);
+    assert_eq!(frame.location(), None);
+    assert_eq!(frame.location_string(), None);
+}
+
+#[test]
+fn test_thread_name() {
+    let bt = thread::Builder::new()
+        .name("worker-3".into())
+        .spawn(|| Backtrace::capture().unwrap())
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(bt.thread_name(), Some("worker-3"));
+    assert!(bt.thread_id().is_some());
+    assert_ne!(bt.thread_id(), Some(thread::current().id()));
+    assert!(bt
+        .to_string()
+        .starts_with("Backtrace (thread \"worker-3\", captured at tests/backtraces.rs:"));
+
+    // the string representation only carries the frames
+    let parsed = Backtrace::from_string_repr(&bt.to_string_repr()).unwrap();
+    assert_eq!(parsed.thread_name(), None);
+    assert_eq!(parsed, bt);
+
+    let bt = thread::spawn(|| Backtrace::capture().unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(bt.thread_name(), None);
+    assert!(bt
+        .to_string()
+        .starts_with("Backtrace (captured at tests/backtraces.rs:"));
+}
+
+#[test]
+fn test_os_error() {
+    let err = std::fs::File::open("/this/path/does/not/exist").unwrap_err();
+    let bt = Backtrace::capture().unwrap();
+    assert!(bt.os_error().is_some());
+    assert_eq!(bt.os_error(), err.raw_os_error());
+
+    // synthetic backtraces carry no capture metadata
+    assert_eq!(synthetic(&["app::main"]).os_error(), None);
+}
+
+#[test]
+fn test_into_frames() {
+    let bt = Backtrace::capture().unwrap();
+    let ips: Vec<_> = bt.frames().iter().map(|x| x.ip()).collect();
+    let rv = Backtrace::from_frames(bt.clone().into_frames());
+    assert_eq!(rv, bt);
+    assert_eq!(rv.frames().iter().map(|x| x.ip()).collect::<Vec<_>>(), ips);
+
+    // frames can be modified before building a new backtrace
+    let mut frames = synthetic(&["app::a", "app::b", "app::c"]).into_frames();
+    frames.remove(1);
+    assert_eq!(
+        names(&Backtrace::from_frames(frames)),
+        vec!["app::a", "app::c"]
+    );
+
+    assert!(Backtrace::empty().into_frames().is_empty());
+}
+
+#[inline(never)]
+fn auto_call_site() -> Backtrace {
+    Backtrace::capture_auto().unwrap()
+}
+
+#[test]
+fn test_capture_auto() {
+    let bt = with_env(&[], auto_call_site);
+    assert!(bt.len() <= 3);
+    assert!(bt.is_truncated());
+    let trimmed = bt.trimmed();
+    assert!(!trimmed.is_empty());
+    let name = trimmed.frames()[0].symbol_name().unwrap();
+    assert!(name.ends_with("auto_call_site"), "{}", name);
+
+    for value in &["1", "full"] {
+        let bt = with_env(&[("RUST_BACKTRACE", value)], auto_call_site);
+        assert!(bt.len() > 3);
+        assert!(!bt.is_truncated());
+        assert_eq!(bt.was_full_requested(), *value == "full");
+    }
+    let trimmed = with_env(&[("RUST_BACKTRACE", "1")], auto_call_site).trimmed();
+    let name = trimmed.frames()[0].symbol_name().unwrap();
+    assert!(name.ends_with("auto_call_site"), "{}", name);
+}
+
+#[test]
+fn test_resolve_with() {
+    let frame = Frame::new(0x1001 as *mut _, AddrHint::Return);
+    assert_eq!(frame.resolution_state(), ResolutionState::Unresolved);
+    let symbols = frame.resolve_with(|ip| {
+        // the resolver sees the address of the call instruction
+        assert_eq!(ip as usize, 0x1000);
+        vec![in_file("runtime::handler", "/vm/handler.js")]
+    });
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(frame.resolution_state(), ResolutionState::Resolved);
+    assert_eq!(frame.symbol_name().as_deref(), Some("runtime::handler"));
+    assert_eq!(
+        frame.symbols()[0].filename(),
+        Some(Path::new("/vm/handler.js"))
+    );
+
+    // resolved frames keep their symbols
+    frame.resolve_with(|_| panic!("resolver invoked"));
+    assert_eq!(frame.symbols().len(), 1);
+    let bt = Backtrace::from_frames(vec![frame]);
+    assert_eq!(names(&bt), vec!["runtime::handler"]);
+}
+
+#[test]
+fn test_nested_capture() {
+    let mut calls = 0;
+    let mut nested = vec![];
+    let bt = Backtrace::capture_filtered(|_| {
+        calls += 1;
+        // re-entering from within the stack walk does not recurse
+        nested.push(Backtrace::capture().unwrap());
+        nested.push(Backtrace::capture_light().unwrap());
+        nested.push(Backtrace::capture_with_limit(10).unwrap());
+        true
+    })
+    .unwrap();
+    assert_eq!(bt.len(), calls);
+    assert_eq!(nested.len(), calls * 3);
+    for bt in &nested {
+        assert!(bt.is_empty());
+    }
+    assert!(nested[0].caller().is_some());
+
+    // capturing works again once the outer capture is done
+    assert!(!Backtrace::capture().unwrap().is_empty());
+}
+
+#[test]
+fn test_debug() {
+    let bt = synthetic(&["app::handler", "app::main"]);
+    assert_eq!(format!("{:?}", bt), "Backtrace { frames: 2 }");
+    assert_eq!(
+        format!("{:?}", Backtrace::empty()),
+        "Backtrace { frames: 0 }"
+    );
+
+    let pretty = format!("{:#?}", bt);
+    assert!(
+        pretty.starts_with("Backtrace {\n    frames: [\n"),
+        "{}",
+        pretty
+    );
+    assert!(pretty.lines().count() > 10);
+    assert!(pretty.contains("\"app::handler\""));
+    assert!(pretty.contains("\"app::main\""));
+    assert!(pretty.contains("ip: 0x"));
+}
@@ -0,0 +1,581 @@
+#[macro_use]
+extern crate defeat;
+
+mod support;
+
+use std::cell::Cell;
+use std::error;
+use std::fmt;
+use std::io;
+use std::panic::Location;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use defeat::{Backtrace, Context, Error, LazyError, Message, ResultExt, StdErrorAdapter};
+
+use support::with_env;
+
+/// An error with an optional origin.
+#[derive(Debug)]
+struct Chained {
+    msg: &'static str,
+    origin: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl Chained {
+    fn new(msg: &'static str) -> Chained {
+        Chained { msg, origin: None }
+    }
+
+    fn wrap<E: Error + Send + Sync + 'static>(msg: &'static str, origin: E) -> Chained {
+        Chained {
+            msg,
+            origin: Some(Box::new(origin)),
+        }
+    }
+}
+
+impl fmt::Display for Chained {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.msg)
+    }
+}
+
+impl Error for Chained {
+    fn sync_origin(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        self.origin.as_deref()
+    }
+}
+
+fn messages<'a, I: Iterator<Item = &'a (dyn Error + 'static)>>(iter: I) -> Vec<String> {
+    iter.map(|x| x.to_string()).collect()
+}
+
+#[test]
+fn test_iter_chain() {
+    let err = Chained::wrap("outer", Chained::wrap("middle", Chained::new("inner")));
+    let err: &dyn Error = &err;
+    assert_eq!(err.iter_chain().count(), 3);
+    assert_eq!(messages(err.iter_chain()), vec!["outer", "middle", "inner"]);
+
+    let err = Chained::new("single");
+    let err: &dyn Error = &err;
+    assert_eq!(messages(err.iter_chain()), vec!["single"]);
+}
+
+#[test]
+fn test_root_cause() {
+    let err = Chained::wrap(
+        "first",
+        Chained::wrap("second", Chained::wrap("third", Chained::new("fourth"))),
+    );
+    let err: &dyn Error = &err;
+    assert_eq!(err.root_cause().to_string(), "fourth");
+
+    let err = Chained::new("alone");
+    let err: &dyn Error = &err;
+    assert_eq!(err.root_cause().to_string(), "alone");
+}
+
+#[derive(Debug)]
+struct TimeoutError;
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl Error for TimeoutError {}
+
+#[test]
+fn test_find_cause() {
+    let err = Chained::wrap("outer", Chained::wrap("middle", TimeoutError));
+    let err: &dyn Error = &err;
+    let found = err.find_cause::<TimeoutError>().unwrap();
+    assert_eq!(found.to_string(), "timed out");
+    assert_eq!(err.find_cause::<Chained>().unwrap().to_string(), "outer");
+
+    let err = Chained::wrap("outer", Chained::new("inner"));
+    let err: &dyn Error = &err;
+    assert!(err.find_cause::<TimeoutError>().is_none());
+}
+
+#[test]
+fn test_context() {
+    let result: Result<(), io::Error> = Err(io::Error::other("disk full"));
+    let err = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || {
+        result.context("could not save").unwrap_err()
+    });
+    assert_eq!(err.to_string(), "could not save");
+    assert_eq!(err.message(), "could not save");
+    assert_eq!(err.backtrace().is_some(), Backtrace::supported());
+
+    let origin = err.origin().unwrap();
+    assert!(origin.is::<io::Error>());
+    assert_eq!(origin.to_string(), "disk full");
+    assert_eq!(err.context_chain(), "could not save: disk full");
+
+    let err = with_env(&[("RUST_ERROR_BACKTRACE", "0")], || {
+        Context::new("wrapped", Chained::new("inner"))
+    });
+    assert!(err.backtrace().is_none());
+    assert_eq!(err.get_ref().to_string(), "inner");
+    let err: &dyn Error = &err;
+    assert_eq!(messages(err.iter_chain()), vec!["wrapped", "inner"]);
+}
+
+fn std_chain(err: &dyn error::Error) -> Vec<String> {
+    let mut rv = vec![err.to_string()];
+    let mut next = err.source();
+    while let Some(err) = next {
+        rv.push(err.to_string());
+        next = err.source();
+    }
+    rv
+}
+
+#[test]
+fn test_std_error_adapter() {
+    let err: Box<dyn Error> = Box::new(Chained::wrap(
+        "outer",
+        Chained::wrap("middle", Chained::new("inner")),
+    ));
+    let adapter = StdErrorAdapter::new(err);
+    assert_eq!(adapter.get_ref().to_string(), "outer");
+
+    let std_err: Box<dyn error::Error> = Box::new(adapter);
+    assert_eq!(std_err.to_string(), "outer");
+    assert!(format!("{:?}", std_err).starts_with("Chained {"));
+    assert_eq!(std_chain(&*std_err), vec!["outer", "middle", "inner"]);
+    assert_eq!(
+        format!("{:?}", std_err.source().unwrap()),
+        format!("{:?}", Chained::wrap("middle", Chained::new("inner")))
+    );
+
+    let adapter = StdErrorAdapter::new(Box::new(Chained::new("alone")));
+    assert!(error::Error::source(&adapter).is_none());
+    assert_eq!(adapter.into_inner().to_string(), "alone");
+}
+
+fn read_config() -> Result<(), io::Error> {
+    Err(io::Error::new(io::ErrorKind::NotFound, "config.toml"))
+}
+
+fn load_sync() -> Result<(), Box<dyn Error + Send + Sync>> {
+    read_config()?;
+    Ok(())
+}
+
+fn load() -> Result<(), Box<dyn Error>> {
+    read_config()?;
+    Ok(())
+}
+
+fn parse_port(s: &str) -> Result<u16, Box<dyn Error + Send + Sync>> {
+    if s.is_empty() {
+        Err(Message::from("empty port"))?;
+    }
+    s.parse()
+        .map_err(|_| Message::from(format!("invalid port {:?}", s)).into())
+}
+
+#[test]
+fn test_question_mark() {
+    let err = load_sync().unwrap_err();
+    assert_eq!(err.to_string(), "config.toml");
+    assert_eq!(
+        err.downcast_ref::<io::Error>().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+
+    let err = load().unwrap_err();
+    assert!(err.is::<io::Error>());
+
+    let err: Box<dyn Error + Send + Sync> = Chained::new("custom").into();
+    assert!(err.is::<Chained>());
+
+    // strings become errors through `Message`
+    assert_eq!(parse_port("80").unwrap(), 80);
+    let err = parse_port("").unwrap_err();
+    assert!(err.is::<Message>());
+    assert_eq!(err.to_string(), "empty port");
+    let err = parse_port("http").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<Message>().unwrap().message(),
+        "invalid port \"http\""
+    );
+    let err = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || {
+        Message::from("with backtrace")
+    });
+    assert_eq!(err.backtrace().is_some(), Backtrace::supported());
+}
+
+#[test]
+fn test_message() {
+    let err = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || {
+        Message::new("something went wrong")
+    });
+    assert_eq!(err.to_string(), "something went wrong");
+    assert_eq!(err.message(), "something went wrong");
+    assert!(err.origin().is_none());
+    assert_eq!(err.backtrace().is_some(), Backtrace::supported());
+
+    let err = with_env(&[], || format_err!("failed after {} attempts", 3));
+    assert_eq!(err.to_string(), "failed after 3 attempts");
+    assert!(err.backtrace().is_none());
+}
+
+/// An error that is its own origin.
+#[derive(Debug)]
+struct Cyclic;
+
+impl fmt::Display for Cyclic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("again")
+    }
+}
+
+impl Error for Cyclic {
+    fn origin(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self)
+    }
+}
+
+#[test]
+fn test_context_chain() {
+    let err = Chained::wrap(
+        "failed to start",
+        Chained::wrap("connection refused", Chained::new("os error 111")),
+    );
+    assert_eq!(
+        err.context_chain(),
+        "failed to start: connection refused: os error 111"
+    );
+    assert_eq!(Chained::new("alone").context_chain(), "alone");
+
+    let chain = Cyclic.context_chain();
+    assert!(chain.starts_with("again: again: "));
+    assert!(chain.ends_with(": ..."));
+    assert_eq!(chain.matches("again").count(), 64);
+}
+
+/// An error wired up the way a derive would do it.
+#[derive(Debug)]
+enum ConfigError {
+    Missing,
+    Io {
+        error: io::Error,
+        backtrace: Option<Backtrace>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Missing => f.write_str("missing config"),
+            ConfigError::Io { .. } => f.write_str("could not read config"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn sync_origin(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        match *self {
+            ConfigError::Missing => None,
+            ConfigError::Io { ref error, .. } => Some(error),
+        }
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        match *self {
+            ConfigError::Missing => None,
+            ConfigError::Io { ref backtrace, .. } => backtrace.as_ref(),
+        }
+    }
+
+    fn set_backtrace(&mut self, bt: Backtrace) {
+        if let ConfigError::Io {
+            ref mut backtrace, ..
+        } = *self
+        {
+            *backtrace = Some(bt);
+        }
+    }
+}
+
+#[test]
+fn test_derive_contract() {
+    let mut err = ConfigError::Io {
+        error: io::Error::new(io::ErrorKind::NotFound, "config.toml"),
+        backtrace: None,
+    };
+    // `origin` defaults to `sync_origin`
+    assert!(err.origin().unwrap().is::<io::Error>());
+    assert!(err.sync_origin().unwrap().is::<io::Error>());
+    assert_eq!(err.context_chain(), "could not read config: config.toml");
+
+    assert!(err.backtrace().is_none());
+    err.set_backtrace(Backtrace::from_location(Location::caller()));
+    assert_eq!(err.backtrace().unwrap().len(), 1);
+
+    // the backtrace is found through wrapping errors
+    let wrapped = with_env(&[], || Context::new("startup failed", err));
+    assert!(wrapped.backtrace().is_none());
+    assert_eq!(wrapped.chain_backtrace().unwrap().len(), 1);
+
+    let mut err = ConfigError::Missing;
+    err.set_backtrace(Backtrace::from_location(Location::caller()));
+    assert!(err.backtrace().is_none());
+    assert!(err.origin().is_none());
+}
+
+#[derive(Debug)]
+struct Transient;
+
+impl fmt::Display for Transient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("try again")
+    }
+}
+
+impl Error for Transient {
+    fn is_transient(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_is_transient() {
+    assert!(Transient.is_transient());
+    assert!(Transient.chain_is_transient());
+
+    let err = Chained::wrap("request failed", Chained::wrap("send", Transient));
+    assert!(!err.is_transient());
+    assert!(err.chain_is_transient());
+
+    let err = with_env(&[], || Context::new("request failed", Transient));
+    assert!(!err.is_transient());
+    assert!(err.chain_is_transient());
+
+    let err = Chained::wrap("request failed", Chained::new("bad request"));
+    assert!(!err.is_transient());
+    assert!(!err.chain_is_transient());
+    assert!(!Cyclic.chain_is_transient());
+}
+
+#[test]
+fn test_ensure_backtrace() {
+    let bt = Backtrace::from_location(Location::caller());
+    let err = ConfigError::Io {
+        error: io::Error::other("disk"),
+        backtrace: Some(bt.clone()),
+    };
+    let rv = with_env(&[("RUST_ERROR_BACKTRACE", "0")], || err.ensure_backtrace());
+    assert_eq!(rv.unwrap().to_string(), bt.to_string());
+
+    let err = ConfigError::Missing;
+    let rv = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || err.ensure_backtrace());
+    assert_eq!(rv.is_some(), Backtrace::supported());
+    // the fallback shows where it was called from
+    if let Some(bt) = rv {
+        assert!(bt.trimmed().to_string().contains("test_ensure_backtrace"));
+    }
+    let rv = with_env(&[("RUST_ERROR_BACKTRACE", "0")], || err.ensure_backtrace());
+    assert!(rv.is_none());
+}
+
+#[test]
+fn test_downcast() {
+    let err: Box<dyn Error> = Box::new(TimeoutError);
+    let err: Box<TimeoutError> = err.downcast().unwrap();
+    assert_eq!(err.to_string(), "timed out");
+
+    let err: Box<dyn Error> = Box::new(Chained::new("not a timeout"));
+    let err = err.downcast::<TimeoutError>().unwrap_err();
+    assert_eq!(err.to_string(), "not a timeout");
+    assert!(err.is::<Chained>());
+
+    let err: Box<dyn Error + Send + Sync> = Box::new(Chained::new("sync"));
+    let err = err.downcast::<TimeoutError>().unwrap_err();
+    let err: Box<Chained> = err.downcast().unwrap();
+    assert_eq!(err.msg, "sync");
+}
+
+/// A link of an error chain that can be closed into a cycle.
+#[derive(Debug)]
+struct Node {
+    name: &'static str,
+    next: OnceLock<&'static Node>,
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl Error for Node {
+    fn origin(&self) -> Option<&(dyn Error + 'static)> {
+        self.next.get().map(|&x| x as &dyn Error)
+    }
+}
+
+fn node(name: &'static str) -> &'static Node {
+    Box::leak(Box::new(Node {
+        name,
+        next: OnceLock::new(),
+    }))
+}
+
+#[test]
+fn test_cyclic_chain() {
+    let a = node("a");
+    let b = node("b");
+    a.next.set(b).unwrap();
+    b.next.set(a).unwrap();
+    let err: &dyn Error = a;
+
+    let links = messages(err.iter_chain());
+    assert_eq!(links.len(), 64);
+    assert_eq!(&links[..4], &["a", "b", "a", "b"]);
+    assert_eq!(err.root_cause().to_string(), "b");
+    assert!(err.find_cause::<TimeoutError>().is_none());
+    assert!(!err.caused_by::<TimeoutError>());
+    assert!(err.chain_backtrace().is_none());
+    assert!(!err.chain_is_transient());
+    let chain = err.context_chain();
+    assert_eq!(chain.split(": ").count(), 65);
+    assert!(chain.ends_with(": ..."));
+    let output = err.display_chain().to_string();
+    assert!(output.lines().count() <= 64 + 3, "{}", output);
+}
+
+#[test]
+fn test_caused_by() {
+    let err = Chained::wrap("outer", Chained::wrap("middle", TimeoutError));
+    let err: &dyn Error = &err;
+    assert!(err.caused_by::<TimeoutError>());
+    assert!(err.caused_by::<Chained>());
+
+    let err = Chained::wrap("outer", Chained::new("inner"));
+    let err: &dyn Error = &err;
+    assert!(!err.caused_by::<TimeoutError>());
+
+    let err: Box<dyn Error + Send + Sync> = Box::new(Chained::wrap("outer", TimeoutError));
+    assert!(err.caused_by::<TimeoutError>());
+}
+
+/// Attaches a backtrace at the catch site if the error has none.
+fn attach_if_missing<E: Error>(err: &mut E, bt: Backtrace) {
+    if err.backtrace().is_none() {
+        err.set_backtrace(bt);
+    }
+}
+
+#[test]
+fn test_set_backtrace() {
+    let mut err = ConfigError::Io {
+        error: io::Error::other("disk"),
+        backtrace: None,
+    };
+    let first = Backtrace::from_location(Location::caller());
+    attach_if_missing(&mut err, first.clone());
+    assert_eq!(err.backtrace().unwrap().to_string(), first.to_string());
+
+    // an existing backtrace is kept
+    let second = Backtrace::from_location(Location::caller());
+    attach_if_missing(&mut err, second.clone());
+    assert_ne!(first.to_string(), second.to_string());
+    assert_eq!(err.backtrace().unwrap().to_string(), first.to_string());
+
+    // types without a backtrace discard it by default
+    let mut err = Chained::new("plain");
+    err.set_backtrace(first);
+    assert!(err.backtrace().is_none());
+}
+
+#[test]
+fn test_display_chain() {
+    let deep = Backtrace::from_location(Location::caller());
+    let err = ConfigError::Io {
+        error: io::Error::other("disk full"),
+        backtrace: Some(deep.clone()),
+    };
+    let err = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || {
+        Context::new("could not start", err)
+    });
+    let err: &dyn Error = &err;
+    let rendered = err.display_chain().to_string();
+    assert!(rendered.starts_with(
+        "Error: could not start\n\n\
+         Caused by:\n    \
+         0: could not read config\n    \
+         1: disk full\n\n"
+    ));
+    // the backtrace of the deepest error that has one is shown
+    assert!(rendered.ends_with(&deep.to_string()), "{}", rendered);
+    assert!(format!("{:#}", err.display_chain()).ends_with(&format!("{:#}", deep)));
+
+    let err = Chained::new("alone");
+    let err: &dyn Error = &err;
+    assert_eq!(err.display_chain().to_string(), "Error: alone");
+}
+
+#[test]
+fn test_chain_backtrace() {
+    let inner = Backtrace::from_location(Location::caller());
+    let err = Chained::wrap(
+        "outer",
+        Chained::wrap(
+            "middle",
+            ConfigError::Io {
+                error: io::Error::other("disk"),
+                backtrace: Some(inner.clone()),
+            },
+        ),
+    );
+    assert!(err.backtrace().is_none());
+    assert_eq!(
+        err.chain_backtrace().unwrap().to_string(),
+        inner.to_string()
+    );
+
+    // a backtrace of the error itself takes precedence
+    let outer = with_env(&[("RUST_ERROR_BACKTRACE", "1")], || {
+        Context::new("wrapped", err)
+    });
+    if let Some(bt) = outer.backtrace() {
+        assert!(std::ptr::eq(outer.chain_backtrace().unwrap(), bt));
+    }
+
+    let err = Chained::wrap("outer", Chained::new("inner"));
+    assert!(err.chain_backtrace().is_none());
+}
+
+#[test]
+fn test_lazy_error() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    let err = LazyError::new("request failed", move || {
+        counter.set(counter.get() + 1);
+        Box::new(Chained::new("connection reset")) as Box<dyn Error>
+    });
+    assert_eq!(err.to_string(), "request failed");
+    assert_eq!(err.message(), "request failed");
+    assert!(!err.is_origin_computed());
+    assert_eq!(calls.get(), 0);
+
+    for _ in 0..3 {
+        assert_eq!(err.origin().unwrap().to_string(), "connection reset");
+    }
+    assert!(err.is_origin_computed());
+    assert_eq!(calls.get(), 1);
+
+    let err: &dyn Error = &err;
+    assert_eq!(
+        messages(err.iter_chain()),
+        vec!["request failed", "connection reset"]
+    );
+    assert_eq!(calls.get(), 1);
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "backtrace")]
+
+extern crate defeat;
+
+use defeat::{set_max_backtrace_frames, Backtrace};
+
+#[inline(never)]
+fn recurse(depth: usize) -> Backtrace {
+    if depth == 0 {
+        Backtrace::capture().unwrap()
+    } else {
+        let rv = recurse(depth - 1);
+        // keep the call out of tail position
+        assert!(!rv.is_empty());
+        rv
+    }
+}
+
+// the limit is global so everything runs in a single test
+#[test]
+fn test_max_backtrace_frames() {
+    let bt = recurse(200);
+    assert_eq!(bt.len(), 128);
+    assert!(bt.is_truncated());
+
+    set_max_backtrace_frames(3);
+    let bt = recurse(10);
+    assert_eq!(bt.len(), 3);
+    assert!(bt.is_truncated());
+    assert_eq!(Backtrace::capture_with_limit(10).unwrap().len(), 3);
+    assert_eq!(Backtrace::capture_filtered(|_| true).unwrap().len(), 3);
+
+    set_max_backtrace_frames(1000);
+    let bt = recurse(200);
+    assert!(bt.len() > 200);
+    assert!(!bt.is_truncated());
+}
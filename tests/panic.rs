@@ -0,0 +1,84 @@
+#![cfg(feature = "backtrace")]
+
+extern crate defeat;
+
+use std::env;
+use std::panic;
+use std::process::Command;
+
+use defeat::install_panic_hook;
+
+/// Panics with the hook installed when run as a child of `test_panic_hook`.
+#[test]
+fn panic_child() {
+    if env::var_os("DEFEAT_PANIC_CHILD").is_none() {
+        return;
+    }
+    install_panic_hook();
+    install_panic_hook();
+    let rv = panic::catch_unwind(|| panic!("boom"));
+    assert!(rv.is_err());
+}
+
+fn run_child(vars: &[(&str, &str)]) -> String {
+    let mut cmd = Command::new(env::current_exe().unwrap());
+    cmd.args(["--exact", "panic_child", "--nocapture", "--test-threads=1"])
+        .env("DEFEAT_PANIC_CHILD", "1")
+        .env_remove("RUST_PANIC_BACKTRACE")
+        .env_remove("RUST_BACKTRACE");
+    for &(name, value) in vars {
+        cmd.env(name, value);
+    }
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Counts the backtraces printed by this crate and the standard library.
+fn count_backtraces(stderr: &str) -> (usize, usize) {
+    (
+        stderr.matches("Backtrace (").count(),
+        stderr.matches("stack backtrace:").count(),
+    )
+}
+
+#[test]
+fn test_panic_hook() {
+    let stderr = run_child(&[("RUST_PANIC_BACKTRACE", "1")]);
+    // the previous hook still runs
+    assert!(stderr.contains("panicked at"), "{}", stderr);
+    assert!(stderr.contains("boom"), "{}", stderr);
+    // installing twice prints only one backtrace
+    assert_eq!(count_backtraces(&stderr), (1, 0), "{}", stderr);
+    // the panic machinery is trimmed off the top
+    let frames: Vec<_> = stderr
+        .lines()
+        .skip_while(|x| !x.starts_with("Backtrace ("))
+        .skip(1)
+        .take_while(|x| x.starts_with("  "))
+        .collect();
+    assert!(frames[0].contains("panic_child"), "{}", stderr);
+    // and the test harness off the bottom
+    assert!(
+        !frames.iter().any(|x| x.contains("short_backtrace")),
+        "{}",
+        stderr
+    );
+
+    let stderr = run_child(&[("RUST_PANIC_BACKTRACE", "0")]);
+    assert!(stderr.contains("panicked at"), "{}", stderr);
+    assert_eq!(count_backtraces(&stderr), (0, 0), "{}", stderr);
+
+    // the default hook prints the backtrace on its own
+    let stderr = run_child(&[("RUST_BACKTRACE", "1")]);
+    assert!(stderr.contains("panicked at"), "{}", stderr);
+    assert_eq!(count_backtraces(&stderr), (0, 1), "{}", stderr);
+
+    let stderr = run_child(&[("RUST_PANIC_BACKTRACE", "full"), ("RUST_BACKTRACE", "0")]);
+    assert_eq!(count_backtraces(&stderr), (1, 0), "{}", stderr);
+    assert!(
+        stderr.contains("__rust_begin_short_backtrace"),
+        "{}",
+        stderr
+    );
+}
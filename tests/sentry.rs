@@ -0,0 +1,69 @@
+#![cfg(all(feature = "sentry", feature = "backtrace"))]
+
+extern crate defeat;
+
+use std::path::PathBuf;
+
+use defeat::{AddrHint, Backtrace, Frame, Symbol};
+
+fn symbol(name: &str, filename: PathBuf, lineno: u32) -> Symbol {
+    Symbol::new(
+        Some(name.as_bytes().to_vec()),
+        None,
+        Some(filename),
+        Some(lineno),
+    )
+}
+
+#[test]
+fn test_to_sentry_value() {
+    let bt = Backtrace::from_frames(vec![
+        Frame::new_resolved(
+            0x1000 as *mut _,
+            AddrHint::Precise,
+            vec![symbol(
+                "_ZN3app7handler17h0123456789abcdefE",
+                PathBuf::from("/src/app/handler.rs"),
+                42,
+            )
+            .with_column(7)],
+        ),
+        Frame::new_resolved(
+            0x2000 as *mut _,
+            AddrHint::Precise,
+            vec![symbol("main", PathBuf::from("/src/main.rs"), 3)],
+        ),
+    ]);
+    let value = bt.to_sentry_value();
+    let frames = value["frames"].as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+
+    // oldest call first, the reverse of `iter_frames()`
+    assert_eq!(frames[0]["function"], "main");
+    assert_eq!(frames[0]["instruction_addr"], "0x2000");
+    assert_eq!(frames[0].get("colno"), None);
+
+    let frame = &frames[1];
+    assert_eq!(frame["function"], "app::handler");
+    assert_eq!(frame["symbol"], "_ZN3app7handler17h0123456789abcdefE");
+    assert_eq!(frame["filename"], "/src/app/handler.rs");
+    assert_eq!(frame["lineno"], 42);
+    assert_eq!(frame["colno"], 7);
+    assert_eq!(frame["instruction_addr"], "0x1000");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_to_sentry_value_invalid_path() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let filename = PathBuf::from(OsString::from_vec(b"/src/\xff.rs".to_vec()));
+    let bt = Backtrace::from_frames(vec![Frame::new_resolved(
+        0x1000 as *mut _,
+        AddrHint::Precise,
+        vec![symbol("main", filename, 1)],
+    )]);
+    let value = bt.to_sentry_value();
+    assert_eq!(value["frames"][0]["filename"], "/src/\u{fffd}.rs");
+}
@@ -0,0 +1,95 @@
+#![cfg(all(feature = "serde", feature = "backtrace"))]
+
+extern crate defeat;
+extern crate serde_json;
+
+use std::path::PathBuf;
+
+use defeat::{AddrHint, Backtrace, Frame, Symbol};
+
+fn roundtrip(bt: &Backtrace) -> Backtrace {
+    let json = serde_json::to_string(bt).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+/// The output without the header, which shows the thread of the capture.
+fn frames_display(output: String) -> String {
+    output.split_once('\n').map_or("", |x| x.1).to_string()
+}
+
+#[test]
+fn test_roundtrip_captured() {
+    let bt = Backtrace::capture().unwrap();
+    let rv = roundtrip(&bt);
+    assert_eq!(rv.len(), bt.len());
+    assert!(!frames_display(bt.to_string()).is_empty());
+    assert_eq!(
+        frames_display(rv.to_string()),
+        frames_display(bt.to_string())
+    );
+    assert_eq!(
+        frames_display(format!("{:#}", rv)),
+        frames_display(format!("{:#}", bt))
+    );
+    assert_eq!(rv, bt);
+
+    let bt = Backtrace::capture_with_limit(2).unwrap();
+    let rv = roundtrip(&bt);
+    assert!(rv.is_truncated());
+    assert_eq!(
+        frames_display(rv.to_string()),
+        frames_display(bt.to_string())
+    );
+}
+
+#[test]
+fn test_roundtrip_synthetic() {
+    let bt = Backtrace::from_frames(vec![Frame::new_resolved(
+        0x1234 as *mut _,
+        AddrHint::Return,
+        vec![Symbol::new(
+            Some(b"_ZN3app7handler17h0123456789abcdefE".to_vec()),
+            Some(0x1200),
+            Some(PathBuf::from("/src/app/handler.rs")),
+            Some(42),
+        )
+        .with_column(7)],
+    )]);
+    let value = serde_json::to_value(&bt).unwrap();
+    let frame = &value["frames"][0];
+    assert_eq!(frame["ip"], "0x1234");
+    assert_eq!(frame["addr_hint"], "return");
+    let symbol = &frame["symbols"][0];
+    assert_eq!(symbol["name"], "_ZN3app7handler17h0123456789abcdefE");
+    assert_eq!(symbol["demangled"], "app::handler");
+    assert_eq!(symbol["addr"], "0x1200");
+    assert_eq!(symbol["filename"], "/src/app/handler.rs");
+    assert_eq!(symbol["lineno"], 42);
+    assert_eq!(symbol["column"], 7);
+
+    let rv: Backtrace = serde_json::from_value(value).unwrap();
+    assert_eq!(format!("{:#}", rv), format!("{:#}", bt));
+    assert_eq!(rv.frames()[0].ip() as usize, 0x1234);
+    assert_eq!(rv.frames()[0].addr_hint(), AddrHint::Return);
+}
+
+#[test]
+fn test_thread_name() {
+    let bt = std::thread::Builder::new()
+        .name("worker-3".into())
+        .spawn(|| Backtrace::capture().unwrap())
+        .unwrap()
+        .join()
+        .unwrap();
+    let value = serde_json::to_value(&bt).unwrap();
+    assert_eq!(value["thread_name"], "worker-3");
+    let rv: Backtrace = serde_json::from_value(value).unwrap();
+    assert_eq!(rv.thread_name(), Some("worker-3"));
+    assert_eq!(rv.thread_id(), None);
+
+    // the field is optional
+    let value = serde_json::to_value(Backtrace::empty()).unwrap();
+    assert!(value.get("thread_name").is_none());
+    let rv: Backtrace = serde_json::from_value(value).unwrap();
+    assert_eq!(rv.thread_name(), None);
+}
@@ -0,0 +1,42 @@
+#![cfg(not(feature = "backtrace"))]
+
+extern crate defeat;
+
+use std::panic::Location;
+
+use defeat::{set_backtrace_strategy, Backtrace, BacktraceStrategy};
+
+struct LocationStrategy;
+
+impl BacktraceStrategy for LocationStrategy {
+    fn capture(&self) -> Option<Backtrace> {
+        Some(Backtrace::from_location(Location::caller()))
+    }
+}
+
+/// Replaces itself on first use, which must not deadlock.
+struct ReplacingStrategy;
+
+impl BacktraceStrategy for ReplacingStrategy {
+    fn capture(&self) -> Option<Backtrace> {
+        set_backtrace_strategy(LocationStrategy);
+        Backtrace::capture()
+    }
+}
+
+#[test]
+fn test_strategy() {
+    assert!(!Backtrace::supported());
+    assert!(Backtrace::capture().is_none());
+
+    set_backtrace_strategy(LocationStrategy);
+    assert!(Backtrace::supported());
+    let bt = Backtrace::capture().unwrap();
+    assert_eq!(bt.len(), 1);
+    assert!(bt.to_string().contains("strategy.rs:"), "{}", bt);
+
+    set_backtrace_strategy(ReplacingStrategy);
+    let bt = Backtrace::capture().unwrap();
+    assert_eq!(bt.len(), 1);
+    assert!(Backtrace::capture().is_some());
+}
@@ -0,0 +1,42 @@
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+/// The variables that control backtrace capturing.
+const BACKTRACE_VARS: &[&str] = &[
+    "RUST_BACKTRACE",
+    "RUST_LIB_BACKTRACE",
+    "RUST_ERROR_BACKTRACE",
+    "RUST_PANIC_BACKTRACE",
+];
+
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `f` with the given environment variables set.
+///
+/// All backtrace variables not listed are removed for the duration of the
+/// call and everything is restored afterwards.  Calls are serialized as the
+/// environment is shared by all tests of the binary.
+pub fn with_env<R, F: FnOnce() -> R>(vars: &[(&str, &str)], f: F) -> R {
+    let _guard = ENV_LOCK.lock().unwrap_or_else(|x| x.into_inner());
+    let mut names: Vec<&str> = BACKTRACE_VARS.to_vec();
+    names.extend(vars.iter().map(|x| x.0));
+    let saved: Vec<_> = names.iter().map(|&x| (x, env::var_os(x))).collect();
+    for &name in &names {
+        env::remove_var(name);
+    }
+    for &(name, value) in vars {
+        env::set_var(name, value);
+    }
+    let rv = panic::catch_unwind(AssertUnwindSafe(f));
+    for (name, value) in saved {
+        match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name),
+        }
+    }
+    match rv {
+        Ok(rv) => rv,
+        Err(err) => panic::resume_unwind(err),
+    }
+}
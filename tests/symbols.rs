@@ -0,0 +1,214 @@
+#![cfg(feature = "backtrace")]
+
+extern crate defeat;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use defeat::{Symbol, SymbolName};
+
+const LEGACY: &str = "_ZN3foo3bar17h0123456789abcdefE";
+const V0: &str = "_RNvCs15kBYyAo9fc_7mycrate7example";
+
+fn name(s: &str) -> SymbolName<'_> {
+    SymbolName::new(s.as_bytes())
+}
+
+#[test]
+fn test_is_rust() {
+    assert!(name(LEGACY).is_rust());
+    assert!(name(&format!("_{}", LEGACY)).is_rust());
+    assert!(name(V0).is_rust());
+    assert!(name(LEGACY).was_demangled());
+    assert!(name(V0).was_demangled());
+
+    assert!(!name("memcpy").is_rust());
+    assert!(!name("memcpy").was_demangled());
+    assert!(!name("_ZN3foo3barE").is_rust());
+}
+
+#[test]
+fn test_demangle_legacy_and_v0() {
+    assert_eq!(name(LEGACY).demangled(), Some("foo::bar"));
+    assert_eq!(name(&format!("_{}", LEGACY)).demangled(), Some("foo::bar"));
+    assert_eq!(name(V0).demangled(), Some("mycrate::example"));
+    assert_eq!(
+        name("_RNvMs_Cs4Cv8Wi1oAIB_7mycrateNtB4_3Foo3bar").demangled(),
+        Some("<mycrate::Foo>::bar")
+    );
+    // a v0 path segment that looks like a legacy hash is not chopped off
+    assert_eq!(
+        name("_RNvCs15kBYyAo9fc_7mycrate17h0123456789abcdef").demangled(),
+        Some("mycrate::h0123456789abcdef")
+    );
+    assert_eq!(name("memcpy").demangled(), Some("memcpy"));
+}
+
+#[test]
+fn test_raw_display() {
+    let name = name(LEGACY);
+    assert_eq!(name.raw_display().to_string(), LEGACY);
+    assert!(name.raw_display().to_string().starts_with("_ZN"));
+    assert_eq!(name.to_string(), "foo::bar");
+    assert!(!name.to_string().contains("_ZN"));
+
+    let invalid = SymbolName::new(b"bad\xffname");
+    assert_eq!(invalid.raw_display().to_string(), "bad\u{fffd}name");
+}
+
+#[test]
+fn test_demangled_name() {
+    let symbol = Symbol::new(Some(LEGACY.as_bytes().to_vec()), None, None, None);
+    let owned = symbol.demangled_name();
+    assert_eq!(owned.as_deref(), symbol.name().unwrap().demangled());
+    assert_eq!(owned.as_deref(), Some("foo::bar"));
+
+    let symbol = Symbol::new(Some(b"memcpy".to_vec()), None, None, None);
+    assert_eq!(symbol.demangled_name().as_deref(), Some("memcpy"));
+    assert_eq!(Symbol::new(None, None, None, None).demangled_name(), None);
+}
+
+#[test]
+#[cfg(feature = "intern")]
+fn test_interned_demangling() {
+    let raw = LEGACY.to_string();
+    let first = name(&raw);
+    let second = SymbolName::new(raw.as_bytes());
+    let a = first.demangled().unwrap();
+    let b = second.demangled().unwrap();
+    assert_eq!(a, "foo::bar");
+    // the second demangle returns the cached allocation
+    assert_eq!(a.as_ptr(), b.as_ptr());
+
+    let other = name(V0);
+    assert_ne!(other.demangled().unwrap().as_ptr(), a.as_ptr());
+}
+
+fn without_generics(s: &str) -> String {
+    name(s).without_generics().unwrap()
+}
+
+#[test]
+fn test_without_generics() {
+    assert_eq!(
+        without_generics("alloc::vec::Vec<u8>::push"),
+        "alloc::vec::Vec::push"
+    );
+    assert_eq!(
+        without_generics("alloc::vec::Vec<alloc::vec::Vec<u8>>::push"),
+        "alloc::vec::Vec::push"
+    );
+    assert_eq!(without_generics("app::parse::<u32>"), "app::parse");
+    assert_eq!(
+        without_generics("app::call::<fn() -> alloc::vec::Vec<u8>>"),
+        "app::call"
+    );
+    assert_eq!(
+        without_generics("<alloc::vec::Vec<T> as core::ops::Drop>::drop"),
+        "<alloc::vec::Vec as core::ops::Drop>::drop"
+    );
+    // qualified path segments keep their brackets
+    assert_eq!(
+        without_generics("core::cmp::impls::<impl core::cmp::PartialOrd for i32>::lt"),
+        "core::cmp::impls::<impl core::cmp::PartialOrd for i32>::lt"
+    );
+    assert_eq!(
+        without_generics("alloc::vec::<impl core::ops::Deref for alloc::vec::Vec<T, A>>::deref"),
+        "alloc::vec::<impl core::ops::Deref for alloc::vec::Vec>::deref"
+    );
+    assert_eq!(without_generics("app::<<T as app::Trait>::Out>"), "app");
+    // names without generics are returned unchanged
+    assert_eq!(without_generics("app::main"), "app::main");
+    assert_eq!(without_generics(LEGACY), "foo::bar");
+    assert_eq!(without_generics("app::broken<u8"), "app::broken<u8");
+}
+
+fn named(s: &str) -> Symbol {
+    Symbol::new(Some(s.as_bytes().to_vec()), None, None, None)
+}
+
+#[test]
+fn test_runtime_classification() {
+    let std = named("std::panicking::begin_panic");
+    assert!(std.is_std() && !std.is_core() && std.is_runtime());
+    let core = named("core::ops::function::FnOnce::call_once");
+    assert!(!core.is_std() && core.is_core() && core.is_runtime());
+    let alloc = named("alloc::vec::Vec<T>::push");
+    assert!(!alloc.is_std() && !alloc.is_core() && alloc.is_runtime());
+    let user = named("app::main");
+    assert!(!user.is_std() && !user.is_core() && !user.is_runtime());
+    // prefixes need to match whole path segments
+    assert!(!named("stdlib::run").is_std());
+    // mangled names are checked after demangling
+    assert!(named("_ZN3std2rt10lang_start17h0123456789abcdefE").is_std());
+    assert!(!Symbol::new(None, None, None, None).is_runtime());
+}
+
+fn at(s: &str, addr: usize, line: u32) -> Symbol {
+    Symbol::new(
+        Some(s.as_bytes().to_vec()),
+        Some(addr),
+        Some(PathBuf::from("/src/app/main.rs")),
+        Some(line),
+    )
+}
+
+#[test]
+// the cached demangled name does not take part in hashing
+#[allow(clippy::mutable_key_type)]
+fn test_symbols_as_keys() {
+    let mut counts = HashMap::new();
+    // the address is ignored, so the first two are the same symbol
+    for symbol in [at("app::run", 0x1000, 10), at("app::run", 0x2000, 10)] {
+        *counts.entry(symbol).or_insert(0) += 1;
+    }
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[&at("app::run", 0x3000, 10)], 2);
+
+    *counts.entry(at("app::run", 0x1000, 11)).or_insert(0) += 1;
+    *counts.entry(at("app::main", 0x1000, 10)).or_insert(0) += 1;
+    assert_eq!(counts.len(), 3);
+
+    let mut names = HashMap::new();
+    for raw in &[LEGACY, LEGACY, V0] {
+        *names.entry(name(raw)).or_insert(0) += 1;
+    }
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&name(LEGACY)], 2);
+    // names compare by their raw bytes, not the demangled form
+    assert_ne!(name(LEGACY), name("foo::bar"));
+}
+
+#[test]
+fn test_mangled_name() {
+    let symbol = named(LEGACY);
+    assert_eq!(symbol.mangled_name().as_deref(), Some(LEGACY));
+    assert_eq!(symbol.name().unwrap().to_string(), "foo::bar");
+    assert_eq!(named(V0).mangled_name().as_deref(), Some(V0));
+    assert_eq!(named("memcpy").mangled_name().as_deref(), Some("memcpy"));
+
+    let invalid = Symbol::new(Some(b"bad\xffname".to_vec()), None, None, None);
+    assert_eq!(invalid.mangled_name().as_deref(), Some("bad\u{fffd}name"));
+    assert_eq!(Symbol::new(None, None, None, None).mangled_name(), None);
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+#[test]
+fn test_wide_name() {
+    let symbol = Symbol::new(None, None, None, None).with_wide_name(&wide(LEGACY));
+    assert_eq!(symbol.name().unwrap().to_string(), "foo::bar");
+    assert_eq!(symbol.mangled_name().as_deref(), Some(LEGACY));
+
+    let symbol = Symbol::new(None, None, None, None).with_wide_name(&wide("Größe::berechnen"));
+    assert_eq!(symbol.name().unwrap().as_str(), Some("Größe::berechnen"));
+    assert_eq!(symbol.name().unwrap().to_string(), "Größe::berechnen");
+
+    // unpaired surrogates are replaced
+    let mut name = wide("app::");
+    name.push(0xd800);
+    let symbol = Symbol::new(None, None, None, None).with_wide_name(&name);
+    assert_eq!(symbol.name().unwrap().to_string(), "app::\u{fffd}");
+}